@@ -0,0 +1,252 @@
+//! Single-threaded executor.
+//!
+//! Tasks live on the thread that calls `block_on`, so they don't need to be
+//! `Send`. Their wakers, on the other hand, may be moved to and fired from any
+//! thread (timers fire from the timing wheel's background thread): a waker only
+//! carries the task id and a handle to the ready queue, never the task itself.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use crate::task::{self, JoinHandle};
+
+/// Id the future passed to `block_on` is scheduled under.
+const MAIN: usize = usize::MAX;
+
+struct ReadyQueue {
+    queue: Mutex<VecDeque<usize>>,
+    tasks_available: Condvar,
+}
+
+impl ReadyQueue {
+    fn push(&self, id: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.push_back(id);
+        drop(queue);
+
+        self.tasks_available.notify_one();
+    }
+
+    fn pop(&self) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+
+        loop {
+            match queue.pop_front() {
+                Some(id) => return id,
+                None => queue = self.tasks_available.wait(queue).unwrap(),
+            }
+        }
+    }
+}
+
+/// What a `Waker` points to. `scheduled` keeps a task from being queued again
+/// while it is already waiting to be polled.
+struct TaskWaker {
+    id: usize,
+    scheduled: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+impl TaskWaker {
+    fn schedule(&self) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.ready.push(self.id);
+        }
+    }
+}
+
+/// Every `RawWaker` built with this table owns one strong count of the
+/// `Arc<TaskWaker>` its data pointer came from (see `waker`).
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone(data: *const ()) -> RawWaker {
+    // SAFETY: `data` comes from `Arc::into_raw` and the waker being cloned
+    // still holds its count, so the allocation is alive.
+    Arc::increment_strong_count(data as *const TaskWaker);
+
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    // SAFETY: `wake` consumes the waker, so this takes over its count.
+    let task = Arc::from_raw(data as *const TaskWaker);
+    task.schedule();
+}
+
+unsafe fn wake_by_ref(data: *const ()) {
+    // SAFETY: the borrowed waker holds a count for as long as this call runs.
+    (*(data as *const TaskWaker)).schedule();
+}
+
+unsafe fn drop_waker(data: *const ()) {
+    // SAFETY: gives back the count owned by the waker being dropped.
+    drop(Arc::from_raw(data as *const TaskWaker));
+}
+
+fn waker(task: &Arc<TaskWaker>) -> Waker {
+    let data = Arc::into_raw(Arc::clone(task)) as *const ();
+
+    // SAFETY: `data` owns the count just taken, as `VTABLE` expects, and
+    // `TaskWaker` is `Send + Sync`, so the waker may be used from any thread.
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    handle: Arc<TaskWaker>,
+    waker: Waker,
+}
+
+struct Shared {
+    ready: Arc<ReadyQueue>,
+    tasks: RefCell<HashMap<usize, Task>>,
+    next_id: Cell<usize>,
+}
+
+/// Executor
+///
+/// Cheap to clone; every clone spawns onto the same ready queue, which is how
+/// tasks spawn further tasks.
+#[derive(Clone)]
+pub struct Executor {
+    shared: Rc<Shared>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            shared: Rc::new(Shared {
+                ready: Arc::new(ReadyQueue {
+                    queue: Mutex::new(VecDeque::new()),
+                    tasks_available: Condvar::default(),
+                }),
+                tasks: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+            }),
+        }
+    }
+
+    /// Queues `future` to run on the next `block_on`.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (future, join) = task::harness(future);
+
+        let id = self.shared.next_id.get();
+        self.shared.next_id.set(id + 1);
+
+        let handle = self.task_waker(id);
+        handle.schedule();
+
+        let task = Task {
+            future: Box::pin(future),
+            waker: waker(&handle),
+            handle,
+        };
+        self.shared.tasks.borrow_mut().insert(id, task);
+
+        join
+    }
+
+    /// Runs `future` to completion, polling spawned tasks whenever they are
+    /// woken. Tasks still pending when `future` completes stay queued.
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        let main = self.task_waker(MAIN);
+        let main_waker = waker(&main);
+        main.schedule();
+
+        loop {
+            match self.shared.ready.pop() {
+                MAIN => {
+                    main.scheduled.store(false, Ordering::Release);
+
+                    let mut cx = Context::from_waker(&main_waker);
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                }
+                id => self.run(id),
+            }
+        }
+    }
+
+    fn run(&self, id: usize) {
+        // Taken out of the map while polled, so it may spawn.
+        let Some(mut task) = self.shared.tasks.borrow_mut().remove(&id) else {
+            return;
+        };
+        task.handle.scheduled.store(false, Ordering::Release);
+
+        let mut cx = Context::from_waker(&task.waker);
+        if task.future.as_mut().poll(&mut cx).is_pending() {
+            self.shared.tasks.borrow_mut().insert(id, task);
+        }
+    }
+
+    fn task_waker(&self, id: usize) -> Arc<TaskWaker> {
+        Arc::new(TaskWaker {
+            id,
+            scheduled: AtomicBool::new(false),
+            ready: Arc::clone(&self.shared.ready),
+        })
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::my_future::MyFuture;
+
+    #[test]
+    fn can_block_on_my_future() {
+        let executor = Executor::new();
+
+        let result = executor.block_on(MyFuture::new(Duration::from_millis(10)));
+
+        assert_eq!(result, "We are done!!!");
+    }
+
+    #[test]
+    fn can_spawn_nested_tasks() {
+        let executor = Executor::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let spawner = executor.clone();
+        let outer_log = Rc::clone(&log);
+        executor.spawn(async move {
+            outer_log.borrow_mut().push("outer");
+
+            let inner_log = Rc::clone(&outer_log);
+            spawner.spawn(async move {
+                MyFuture::new(Duration::from_millis(10)).await;
+                inner_log.borrow_mut().push("inner");
+            });
+        });
+
+        executor.block_on(MyFuture::new(Duration::from_millis(200)));
+
+        assert_eq!(*log.borrow(), vec!["outer", "inner"]);
+    }
+}
@@ -0,0 +1,558 @@
+//! Combinators for running several futures from one task.
+//!
+//! `join!` and `select!` are for a handful of futures of different types,
+//! `FuturesUnordered` (and `join_all`/`try_join_all` on top of it) for any
+//! number of futures of one type.
+
+use std::{
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+/// Polls every future until all of them complete, then evaluates to a tuple
+/// of their outputs. Only usable inside `async` code.
+#[macro_export]
+macro_rules! join {
+    // Every `future` below comes from a different expansion, so hygiene keeps
+    // them apart as if they had different names.
+    (@bind [$($future:ident)*]) => {{
+        ::std::future::poll_fn(|cx| {
+            let mut ready = true;
+            $( ready &= $future.poll(cx); )*
+
+            if ready {
+                ::std::task::Poll::Ready(($($future.take_output(),)*))
+            } else {
+                ::std::task::Poll::Pending
+            }
+        })
+        .await
+    }};
+    (@bind [$($done:ident)*] $head:expr, $($rest:tt)*) => {{
+        let mut future = $crate::future::MaybeDone::new($head);
+        $crate::join!(@bind [$($done)* future] $($rest)*)
+    }};
+    ($($future:expr),+ $(,)?) => {
+        $crate::join!(@bind [] $($future,)+)
+    };
+}
+
+/// Waits on several futures and runs the branch of the first one to complete,
+/// dropping the others:
+///
+/// ```ignore
+/// select! {
+///     Some(message) = receiver.recv() => handle(message),
+///     () = sleep(timeout) => give_up(),
+///     else => unreachable!(),
+/// }
+/// ```
+///
+/// Branches are polled in the order written. A branch whose output doesn't
+/// match its pattern is disabled and the others keep running; the optional
+/// `else` branch runs once every branch is disabled, and without it that
+/// panics. Only usable inside `async` code.
+#[macro_export]
+macro_rules! select {
+    (@run [$( ($future:ident $output:ident $pattern:pat = $body:expr) )*] else => $else:expr) => {{
+        loop {
+            let progressed = ::std::future::poll_fn(|cx| {
+                let mut pending = false;
+                $(
+                    if let ::std::option::Option::Some(running) = $future.as_mut() {
+                        match ::std::future::Future::poll(running.as_mut(), cx) {
+                            ::std::task::Poll::Ready(value) => {
+                                $output = ::std::option::Option::Some(value);
+                                $future = ::std::option::Option::None;
+                                return ::std::task::Poll::Ready(true);
+                            }
+                            ::std::task::Poll::Pending => pending = true,
+                        }
+                    }
+                )*
+
+                if pending {
+                    ::std::task::Poll::Pending
+                } else {
+                    ::std::task::Poll::Ready(false)
+                }
+            })
+            .await;
+
+            if !progressed {
+                break;
+            }
+
+            $(
+                #[allow(unused_variables, unreachable_patterns)]
+                let matched = match &$output {
+                    ::std::option::Option::Some($pattern) => true,
+                    _ => false,
+                };
+                if !matched {
+                    $output = ::std::option::Option::None;
+                }
+            )*
+
+            if false $( || $output.is_some() )* {
+                break;
+            }
+        }
+
+        $(
+            if let ::std::option::Option::Some($pattern) = $output.take() {
+                $body
+            } else
+        )* {
+            $else
+        }
+    }};
+    (@bind [$($done:tt)*] else => $else:expr $(,)?) => {
+        $crate::select!(@run [$($done)*] else => $else)
+    };
+    (@bind [$($done:tt)*]) => {
+        $crate::select!(@run [$($done)*] else => ::std::panic!("select!: every branch is disabled"))
+    };
+    (@bind [$($done:tt)*] $pattern:pat = $future:expr => $body:expr $(, $($rest:tt)*)?) => {{
+        let mut future = ::std::option::Option::Some(::std::boxed::Box::pin($future));
+        let mut output = ::std::option::Option::None;
+        $crate::select!(@bind [$($done)* (future output $pattern = $body)] $($($rest)*)?)
+    }};
+    ($($tokens:tt)+) => {
+        $crate::select!(@bind [] $($tokens)+)
+    };
+}
+
+/// Used by `join!`: a future, then its output once it completed.
+#[doc(hidden)]
+pub enum MaybeDone<F: Future> {
+    Pending(Pin<Box<F>>),
+    Done(Option<F::Output>),
+}
+
+impl<F: Future> MaybeDone<F> {
+    pub fn new(future: F) -> Self {
+        Self::Pending(Box::pin(future))
+    }
+
+    /// Whether the output is available.
+    pub fn poll(&mut self, cx: &mut Context<'_>) -> bool {
+        match self {
+            Self::Pending(future) => match future.as_mut().poll(cx) {
+                Poll::Ready(output) => {
+                    *self = Self::Done(Some(output));
+                    true
+                }
+                Poll::Pending => false,
+            },
+            Self::Done(_) => true,
+        }
+    }
+
+    pub fn take_output(&mut self) -> F::Output {
+        match self {
+            Self::Done(output) => output.take().expect("output already taken"),
+            Self::Pending(_) => panic!("future has not completed"),
+        }
+    }
+}
+
+/// Indices of the children whose wakers fired, plus the waker of whoever
+/// polls the set.
+struct ReadyQueue {
+    indices: Mutex<VecDeque<usize>>,
+    parent: Mutex<Option<Waker>>,
+}
+
+struct Child {
+    index: usize,
+    /// Keeps an index from being queued twice.
+    queued: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for Child {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.ready.indices.lock().unwrap().push_back(self.index);
+
+        let parent = self.ready.parent.lock().unwrap().clone();
+        if let Some(parent) = parent {
+            parent.wake();
+        }
+    }
+}
+
+struct Slot<F> {
+    future: Pin<Box<F>>,
+    child: Arc<Child>,
+    waker: Waker,
+}
+
+/// FuturesUnordered
+///
+/// A set of futures that yields their outputs in completion order. Every
+/// future gets its own waker, so a poll only touches the futures that were
+/// woken since the last one, not the whole set.
+pub struct FuturesUnordered<F> {
+    slots: Vec<Option<Slot<F>>>,
+    free: Vec<usize>,
+    ready: Arc<ReadyQueue>,
+    len: usize,
+}
+
+impl<F: Future> FuturesUnordered<F> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            ready: Arc::new(ReadyQueue {
+                indices: Mutex::new(VecDeque::new()),
+                parent: Mutex::new(None),
+            }),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The future is first polled by the next `poll_next`.
+    pub fn push(&mut self, future: F) {
+        let index = self.free.pop().unwrap_or(self.slots.len());
+        if index == self.slots.len() {
+            self.slots.push(None);
+        }
+
+        let child = Arc::new(Child {
+            index,
+            queued: AtomicBool::new(false),
+            ready: Arc::clone(&self.ready),
+        });
+        let waker = Waker::from(Arc::clone(&child));
+        waker.wake_by_ref();
+
+        self.slots[index] = Some(Slot {
+            future: Box::pin(future),
+            child,
+            waker,
+        });
+        self.len += 1;
+    }
+
+    /// `Ready(None)` once the set is empty.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<F::Output>> {
+        if self.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        // Registered before looking at the queue, so no wake-up is lost.
+        match &mut *self.ready.parent.lock().unwrap() {
+            Some(parent) if parent.will_wake(cx.waker()) => {}
+            parent => *parent = Some(cx.waker().clone()),
+        }
+
+        // A future that keeps waking itself must not keep us here forever.
+        let mut budget = self.ready.indices.lock().unwrap().len();
+
+        while budget > 0 {
+            budget -= 1;
+
+            let Some(index) = self.ready.indices.lock().unwrap().pop_front() else {
+                return Poll::Pending;
+            };
+            // A stale wake-up for a slot since reused is only a spurious poll.
+            let Some(slot) = self.slots[index].as_mut() else {
+                continue;
+            };
+            slot.child.queued.store(false, Ordering::Release);
+
+            let mut child_cx = Context::from_waker(&slot.waker);
+            if let Poll::Ready(output) = slot.future.as_mut().poll(&mut child_cx) {
+                self.slots[index] = None;
+                self.free.push(index);
+                self.len -= 1;
+
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if !self.ready.indices.lock().unwrap().is_empty() {
+            cx.waker().wake_by_ref();
+        }
+
+        Poll::Pending
+    }
+
+    pub async fn next(&mut self) -> Option<F::Output> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl<F: Future> Default for FuturesUnordered<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: Future> FromIterator<F> for FuturesUnordered<F> {
+    fn from_iter<I: IntoIterator<Item = F>>(futures: I) -> Self {
+        let mut set = Self::new();
+        futures.into_iter().for_each(|future| set.push(future));
+
+        set
+    }
+}
+
+/// Runs every future concurrently; the outputs keep the input order.
+pub async fn join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output> {
+    let mut set: FuturesUnordered<_> = futures
+        .into_iter()
+        .enumerate()
+        .map(|(index, future)| async move { (index, future.await) })
+        .collect();
+
+    let mut outputs: Vec<_> = (0..set.len()).map(|_| None).collect();
+    while let Some((index, output)) = set.next().await {
+        outputs[index] = Some(output);
+    }
+
+    outputs.into_iter().flatten().collect()
+}
+
+/// Like `join_all`, but stops at the first error and drops the futures that
+/// are still running.
+pub async fn try_join_all<F, T, E>(futures: impl IntoIterator<Item = F>) -> Result<Vec<T>, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let mut set: FuturesUnordered<_> = futures
+        .into_iter()
+        .enumerate()
+        .map(|(index, future)| async move { (index, future.await) })
+        .collect();
+
+    let mut outputs: Vec<_> = (0..set.len()).map(|_| None).collect();
+    while let Some((index, output)) = set.next().await {
+        outputs[index] = Some(output?);
+    }
+
+    Ok(outputs.into_iter().flatten().collect())
+}
+
+/// Output of whichever future completes first, `first` winning ties. The
+/// other one is dropped.
+pub async fn race<T>(first: impl Future<Output = T>, second: impl Future<Output = T>) -> T {
+    let mut first = Box::pin(first);
+    let mut second = Box::pin(second);
+
+    poll_fn(|cx| match first.as_mut().poll(cx) {
+        Poll::Ready(output) => Poll::Ready(output),
+        Poll::Pending => second.as_mut().poll(cx),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use crate::{
+        executor::Executor,
+        sync::Notify,
+        time::{sleep, Sleep},
+    };
+
+    #[test]
+    fn join_waits_for_everything() {
+        let executor = Executor::new();
+
+        let (number, text, ()) = executor.block_on(async {
+            crate::join!(
+                async {
+                    sleep(Duration::from_millis(10)).await;
+                    1
+                },
+                async { "two" },
+                sleep(Duration::from_millis(5)),
+            )
+        });
+
+        assert_eq!((number, text), (1, "two"));
+    }
+
+    #[test]
+    fn select_runs_the_first_branch_to_complete() {
+        let executor = Executor::new();
+
+        let winner = executor.block_on(async {
+            crate::select! {
+                () = sleep(Duration::from_secs(3600)) => "slow",
+                value = async {
+                    sleep(Duration::from_millis(5)).await;
+                    "fast"
+                } => value,
+            }
+        });
+
+        assert_eq!(winner, "fast");
+    }
+
+    #[test]
+    fn select_disables_branches_that_do_not_match() {
+        let executor = Executor::new();
+
+        let winner = executor.block_on(async {
+            crate::select! {
+                Some(value) = async { None::<i32> } => value,
+                () = sleep(Duration::from_millis(5)) => -1,
+            }
+        });
+        assert_eq!(winner, -1);
+
+        let fallback = executor.block_on(async {
+            crate::select! {
+                Some(value) = async { None::<i32> } => value,
+                else => 0,
+            }
+        });
+        assert_eq!(fallback, 0);
+    }
+
+    #[test]
+    fn join_all_keeps_input_order() {
+        let executor = Executor::new();
+
+        let outputs = executor.block_on(join_all((0..5u64).rev().map(|delay| async move {
+            sleep(Duration::from_millis(delay * 2)).await;
+            delay
+        })));
+
+        assert_eq!(outputs, vec![4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn try_join_all_stops_at_the_first_error() {
+        let executor = Executor::new();
+
+        type Boxed = Pin<Box<dyn Future<Output = Result<u8, &'static str>>>>;
+        let futures: Vec<Boxed> = vec![
+            Box::pin(async { Ok(1) }),
+            Box::pin(async {
+                sleep(Duration::from_secs(3600)).await;
+                Ok(2)
+            }),
+            Box::pin(async { Err("failed") }),
+        ];
+
+        assert_eq!(executor.block_on(try_join_all(futures)), Err("failed"));
+        assert_eq!(
+            executor.block_on(try_join_all([async { Ok::<_, ()>(1) }])),
+            Ok(vec![1])
+        );
+    }
+
+    #[test]
+    fn race_returns_the_first_output() {
+        let executor = Executor::new();
+
+        let winner = executor.block_on(race(
+            async {
+                sleep(Duration::from_secs(3600)).await;
+                "slow"
+            },
+            async { "fast" },
+        ));
+
+        assert_eq!(winner, "fast");
+    }
+
+    struct CountPolls<F> {
+        future: Pin<Box<F>>,
+        polls: Rc<Cell<usize>>,
+    }
+
+    impl<F: Future> Future for CountPolls<F> {
+        type Output = F::Output;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.polls.set(self.polls.get() + 1);
+            self.future.as_mut().poll(cx)
+        }
+    }
+
+    #[test]
+    fn only_woken_futures_are_polled_again() {
+        let executor = Executor::new();
+        let polls = Rc::new(Cell::new(0));
+        let notifies: Rc<Vec<Notify>> = Rc::new((0..1000).map(|_| Notify::new()).collect());
+
+        let mut set: FuturesUnordered<_> = (0..1000)
+            .map(|index| {
+                let notifies = Rc::clone(&notifies);
+                CountPolls {
+                    future: Box::pin(async move {
+                        notifies[index].notified().await;
+                        index
+                    }),
+                    polls: Rc::clone(&polls),
+                }
+            })
+            .collect();
+
+        executor.block_on(async {
+            poll_fn(|cx| {
+                assert!(set.poll_next(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+
+            for index in (0..1000).rev() {
+                notifies[index].notify_one();
+                assert_eq!(set.next().await, Some(index));
+            }
+            assert_eq!(set.next().await, None);
+        });
+
+        // One poll to register, one to complete.
+        assert_eq!(polls.get(), 2000);
+    }
+
+    #[test]
+    fn can_drive_many_timers() {
+        let executor = Executor::new();
+
+        let mut set: FuturesUnordered<Sleep> = (0..1000)
+            .map(|index| sleep(Duration::from_millis(index % 20)))
+            .collect();
+
+        let completed = executor.block_on(async {
+            let mut completed = 0;
+            while set.next().await.is_some() {
+                completed += 1;
+            }
+            completed
+        });
+
+        assert_eq!(completed, 1000);
+    }
+}
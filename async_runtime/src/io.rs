@@ -0,0 +1,117 @@
+//! Poll-based I/O traits, plus the `async fn`-style helpers built on them.
+
+use std::{
+    future::{poll_fn, Future},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// AsyncRead
+///
+/// Reads into `buf`, returning `Ok(0)` at end of stream.
+pub trait AsyncRead {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+/// AsyncWrite
+pub trait AsyncWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>;
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Closes the writing half; the peer reads end of stream.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+pub trait AsyncReadExt: AsyncRead + Unpin {
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> impl Future<Output = io::Result<usize>> + 'a {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_read(cx, buf))
+    }
+
+    /// Fails with `UnexpectedEof` if the stream ends before `buf` is full.
+    fn read_exact<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<()>> + 'a {
+        let mut filled = 0;
+
+        poll_fn(move |cx| {
+            while filled < buf.len() {
+                match Pin::new(&mut *self).poll_read(cx, &mut buf[filled..]) {
+                    Poll::Ready(Ok(0)) => {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    Poll::Ready(Ok(read)) => filled += read,
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            Poll::Ready(Ok(()))
+        })
+    }
+
+    /// Reads until end of stream, returning how many bytes were appended.
+    fn read_to_end<'a>(
+        &'a mut self,
+        buf: &'a mut Vec<u8>,
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        let start = buf.len();
+        let mut chunk = [0; 4096];
+
+        poll_fn(move |cx| loop {
+            match Pin::new(&mut *self).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Ok(buf.len() - start)),
+                Poll::Ready(Ok(read)) => buf.extend_from_slice(&chunk[..read]),
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        })
+    }
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> AsyncReadExt for R {}
+
+pub trait AsyncWriteExt: AsyncWrite + Unpin {
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> impl Future<Output = io::Result<usize>> + 'a {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_write(cx, buf))
+    }
+
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> impl Future<Output = io::Result<()>> + 'a {
+        let mut written = 0;
+
+        poll_fn(move |cx| {
+            while written < buf.len() {
+                match Pin::new(&mut *self).poll_write(cx, &buf[written..]) {
+                    Poll::Ready(Ok(0)) => {
+                        return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                    }
+                    Poll::Ready(Ok(count)) => written += count,
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            Poll::Ready(Ok(()))
+        })
+    }
+
+    fn flush(&mut self) -> impl Future<Output = io::Result<()>> + '_ {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_flush(cx))
+    }
+
+    fn shutdown(&mut self) -> impl Future<Output = io::Result<()>> + '_ {
+        poll_fn(move |cx| Pin::new(&mut *self).poll_shutdown(cx))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWriteExt for W {}
@@ -0,0 +1,11 @@
+pub mod executor;
+pub mod future;
+pub mod io;
+pub mod my_future;
+pub mod net;
+mod reactor;
+pub mod runtime;
+pub mod sim;
+pub mod sync;
+pub mod task;
+pub mod time;
@@ -0,0 +1,33 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::time::{sleep, Sleep};
+
+pub struct MyFuture {
+    sleep: Sleep,
+}
+
+impl MyFuture {
+    pub fn new(duration: Duration) -> Self {
+        MyFuture {
+            sleep: sleep(duration),
+        }
+    }
+}
+
+impl Future for MyFuture {
+    type Output = String;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.sleep).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(()) => Poll::Ready("We are done!!!".to_owned()),
+        }
+    }
+}
+
+// https://www.turing.com/interview-questions/rust, Q34
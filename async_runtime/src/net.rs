@@ -0,0 +1,393 @@
+//! TCP and UDP sockets driven by the reactor.
+//!
+//! Each type wraps the `std::net` socket in non-blocking mode. Every operation
+//! is tried directly and only waits on the reactor after `WouldBlock`.
+
+use std::{
+    future::poll_fn,
+    io::{self, Read, Write},
+    mem,
+    net::{self, Shutdown, SocketAddr, ToSocketAddrs},
+    os::fd::{AsRawFd, FromRawFd},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    io::{AsyncRead, AsyncWrite},
+    reactor::{Interest, Reactor, Registration},
+};
+
+/// TcpListener
+pub struct TcpListener {
+    // Declared first so it is dropped while the socket is still open.
+    registration: Registration,
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let inner = net::TcpListener::bind(addr)?;
+        inner.set_nonblocking(true)?;
+
+        Ok(Self {
+            registration: Reactor::get().register(inner.as_raw_fd())?,
+            inner,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Read, || self.inner.accept())
+        })
+        .await?;
+
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+/// TcpStream
+pub struct TcpStream {
+    registration: Registration,
+    inner: net::TcpStream,
+}
+
+impl TcpStream {
+    /// Connects without blocking the thread: the socket is created
+    /// non-blocking and the task waits for it to become writable.
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe {
+            libc::socket(
+                domain,
+                libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { net::TcpStream::from_raw_fd(fd) };
+
+        let (raw, len) = raw_socket_addr(&addr);
+        let result = unsafe { libc::connect(fd, &raw as *const _ as *const libc::sockaddr, len) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(error);
+            }
+        }
+
+        let stream = Self::from_std(socket)?;
+        poll_fn(|cx| {
+            stream.registration.poll_io(cx, Interest::Write, || {
+                if let Some(error) = stream.inner.take_error()? {
+                    return Err(error);
+                }
+
+                match stream.inner.peer_addr() {
+                    Err(error) if error.kind() == io::ErrorKind::NotConnected => {
+                        Err(io::ErrorKind::WouldBlock.into())
+                    }
+                    result => result.map(|_| ()),
+                }
+            })
+        })
+        .await?;
+
+        Ok(stream)
+    }
+
+    fn from_std(inner: net::TcpStream) -> io::Result<Self> {
+        inner.set_nonblocking(true)?;
+
+        Ok(Self {
+            registration: Reactor::get().register(inner.as_raw_fd())?,
+            inner,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        this.registration
+            .poll_io(cx, Interest::Read, || (&this.inner).read(buf))
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        this.registration
+            .poll_io(cx, Interest::Write, || (&this.inner).write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.inner.shutdown(Shutdown::Write))
+    }
+}
+
+/// UdpSocket
+pub struct UdpSocket {
+    registration: Registration,
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let inner = net::UdpSocket::bind(addr)?;
+        inner.set_nonblocking(true)?;
+
+        Ok(Self {
+            registration: Reactor::get().register(inner.as_raw_fd())?,
+            inner,
+        })
+    }
+
+    /// Sets the default peer for `send` and filters what `recv` accepts.
+    pub fn connect(&self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Write, || self.inner.send_to(buf, target))
+        })
+        .await
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Read, || self.inner.recv_from(buf))
+        })
+        .await
+    }
+
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Write, || self.inner.send(buf))
+        })
+        .await
+    }
+
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_fn(|cx| {
+            self.registration
+                .poll_io(cx, Interest::Read, || self.inner.recv(buf))
+        })
+        .await
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+fn raw_socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let raw = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: addr.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(raw) };
+
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let raw = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(raw) };
+
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{rc::Rc, sync::mpsc, time::Duration};
+
+    use crate::{
+        executor::Executor,
+        io::{AsyncReadExt, AsyncWriteExt},
+        runtime::{self, Runtime},
+        time::{sleep, timeout},
+    };
+
+    #[test]
+    fn can_echo_over_tcp() {
+        let runtime = Runtime::new(2);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        runtime.spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                runtime::spawn(async move {
+                    let mut buf = [0; 1024];
+                    loop {
+                        match stream.read(&mut buf).await.unwrap() {
+                            0 => break,
+                            read => stream.write_all(&buf[..read]).await.unwrap(),
+                        }
+                    }
+                });
+            }
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        for client in 0..8u8 {
+            let sender = sender.clone();
+            runtime.spawn(async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                let message = vec![client; 100_000];
+
+                stream.write_all(&message).await.unwrap();
+                stream.shutdown().await.unwrap();
+
+                let mut echoed = Vec::new();
+                stream.read_to_end(&mut echoed).await.unwrap();
+                sender.send(echoed == message).unwrap();
+            });
+        }
+        drop(sender);
+
+        assert!(receiver.iter().all(|matched| matched));
+    }
+
+    #[test]
+    fn wakes_every_task_accepting_on_one_listener() {
+        let executor = Executor::new();
+        let listener = Rc::new(TcpListener::bind("127.0.0.1:0").unwrap());
+        let addr = listener.local_addr().unwrap();
+
+        let accepts: Vec<_> = (0..2)
+            .map(|_| {
+                let listener = Rc::clone(&listener);
+                executor.spawn(async move { listener.accept().await.map(drop) })
+            })
+            .collect();
+
+        let accepted = executor.block_on(async {
+            // Both tasks are waiting on the listener by now.
+            sleep(Duration::from_millis(10)).await;
+            let _clients = [
+                net::TcpStream::connect(addr).unwrap(),
+                net::TcpStream::connect(addr).unwrap(),
+            ];
+
+            timeout(Duration::from_secs(5), async {
+                for accept in accepts {
+                    accept.await.unwrap().unwrap();
+                }
+            })
+            .await
+        });
+
+        assert!(accepted.is_ok());
+    }
+
+    #[test]
+    fn connect_reports_refused_connections() {
+        let executor = Executor::new();
+        let addr = net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let result = executor.block_on(TcpStream::connect(addr));
+
+        assert_eq!(
+            result.err().map(|error| error.kind()),
+            Some(io::ErrorKind::ConnectionRefused)
+        );
+    }
+
+    #[test]
+    fn can_echo_over_udp() {
+        let executor = Executor::new();
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        executor.spawn(async move {
+            let mut buf = [0; 1024];
+            loop {
+                let (read, peer) = server.recv_from(&mut buf).await.unwrap();
+                server.send_to(&buf[..read], peer).await.unwrap();
+            }
+        });
+
+        let echoed = executor.block_on(async {
+            let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.connect(server_addr).unwrap();
+
+            let mut echoed = Vec::new();
+            for message in [&b"ping"[..], b"pong"] {
+                client.send(message).await.unwrap();
+
+                let mut buf = [0; 1024];
+                let read = client.recv(&mut buf).await.unwrap();
+                echoed.push(buf[..read].to_vec());
+            }
+            echoed
+        });
+
+        assert_eq!(echoed, vec![b"ping".to_vec(), b"pong".to_vec()]);
+    }
+}
@@ -0,0 +1,243 @@
+//! epoll based I/O reactor.
+//!
+//! Sockets are registered edge-triggered with a single epoll instance, which a
+//! background thread waits on. When a socket becomes readable or writable the
+//! thread records it and wakes the task waiting for that direction.
+//!
+//! Readiness starts out optimistic: the first operation on a new socket is
+//! simply attempted, and only a `WouldBlock` makes the task wait for the next
+//! edge.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Interest {
+    Read,
+    Write,
+}
+
+struct Direction {
+    ready: bool,
+    /// Bumped on every readiness event, so a `WouldBlock` only clears the
+    /// readiness it actually observed.
+    tick: u64,
+    /// Every task waiting for this direction, since sockets may be shared.
+    wakers: Vec<Waker>,
+}
+
+struct ScheduledIo {
+    directions: Mutex<[Direction; 2]>,
+}
+
+impl ScheduledIo {
+    fn set_ready(&self, read: bool, write: bool) {
+        let mut directions = self.directions.lock().unwrap();
+        let mut wakers = Vec::new();
+
+        for (direction, ready) in directions.iter_mut().zip([read, write]) {
+            if ready {
+                direction.ready = true;
+                direction.tick += 1;
+                wakers.append(&mut direction.wakers);
+            }
+        }
+        drop(directions);
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+pub(crate) struct Reactor {
+    epoll: OwnedFd,
+    sources: Mutex<HashMap<u64, Arc<ScheduledIo>>>,
+    next_token: AtomicU64,
+}
+
+impl Reactor {
+    pub(crate) fn get() -> &'static Reactor {
+        static REACTOR: OnceLock<Reactor> = OnceLock::new();
+
+        let mut started = false;
+        let reactor = REACTOR.get_or_init(|| {
+            let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+            if epoll < 0 {
+                panic!("epoll_create1 failed: {}", io::Error::last_os_error());
+            }
+
+            started = true;
+            Reactor {
+                epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+                sources: Mutex::new(HashMap::new()),
+                next_token: AtomicU64::new(0),
+            }
+        });
+
+        if started {
+            thread::Builder::new()
+                .name("reactor".to_owned())
+                .spawn(move || reactor.run())
+                .unwrap();
+        }
+
+        reactor
+    }
+
+    fn run(&self) {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; 1024];
+
+        loop {
+            let ready = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    events.len() as i32,
+                    -1,
+                )
+            };
+
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                panic!("epoll_wait failed: {error}");
+            }
+
+            for event in &events[..ready as usize] {
+                let token = event.u64;
+                let flags = event.events as i32;
+
+                let Some(io) = self.sources.lock().unwrap().get(&token).cloned() else {
+                    continue;
+                };
+
+                let closed = flags & (libc::EPOLLHUP | libc::EPOLLERR) != 0;
+                io.set_ready(
+                    closed || flags & (libc::EPOLLIN | libc::EPOLLRDHUP) != 0,
+                    closed || flags & libc::EPOLLOUT != 0,
+                );
+            }
+        }
+    }
+
+    pub(crate) fn register(&'static self, fd: RawFd) -> io::Result<Registration> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let io = Arc::new(ScheduledIo {
+            directions: Mutex::new([0, 1].map(|_| Direction {
+                ready: true,
+                tick: 0,
+                wakers: Vec::new(),
+            })),
+        });
+        self.sources.lock().unwrap().insert(token, Arc::clone(&io));
+
+        let mut event = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET) as u32,
+            u64: token,
+        };
+        let result =
+            unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if result < 0 {
+            self.sources.lock().unwrap().remove(&token);
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Registration {
+            reactor: self,
+            fd,
+            token,
+            io,
+        })
+    }
+}
+
+/// Registration
+///
+/// Ties a file descriptor to the reactor until dropped. Must be dropped before
+/// the descriptor is closed.
+pub(crate) struct Registration {
+    reactor: &'static Reactor,
+    fd: RawFd,
+    token: u64,
+    io: Arc<ScheduledIo>,
+}
+
+impl Registration {
+    /// Every task that polled since the last readiness event is woken by the
+    /// next one.
+    pub(crate) fn poll_ready(&self, cx: &mut Context<'_>, interest: Interest) -> Poll<u64> {
+        let mut directions = self.io.directions.lock().unwrap();
+        let direction = &mut directions[interest as usize];
+
+        if direction.ready {
+            return Poll::Ready(direction.tick);
+        }
+
+        if !direction
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            direction.wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+
+    fn clear_readiness(&self, interest: Interest, tick: u64) {
+        let mut directions = self.io.directions.lock().unwrap();
+        let direction = &mut directions[interest as usize];
+
+        if direction.tick == tick {
+            direction.ready = false;
+        }
+    }
+
+    /// Runs the non-blocking `operation` until it stops failing with
+    /// `WouldBlock`, waiting for readiness in between.
+    pub(crate) fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        interest: Interest,
+        mut operation: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let tick = match self.poll_ready(cx, interest) {
+                Poll::Ready(tick) => tick,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match operation() {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    self.clear_readiness(interest, tick);
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        unsafe {
+            libc::epoll_ctl(
+                self.reactor.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                self.fd,
+                std::ptr::null_mut(),
+            );
+        }
+
+        self.reactor.sources.lock().unwrap().remove(&self.token);
+    }
+}
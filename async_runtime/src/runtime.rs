@@ -0,0 +1,708 @@
+//! Multi-threaded, work-stealing runtime.
+//!
+//! Every worker owns a local run queue. Tasks woken on a worker go to that
+//! worker's queue, tasks spawned or woken from any other thread go to the
+//! shared injector. A worker that runs out of local work first checks the
+//! injector, then steals half of the queue of a randomly chosen sibling, and
+//! parks once there is nothing left anywhere.
+//!
+//! Every task also keeps a few counters, and `Runtime::dump` lists them for all
+//! live tasks, which is the first thing to look at when something hangs.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    panic::Location,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::task::{self, Dump, JoinHandle, TaskSnapshot, TaskState};
+
+/// Every this many tasks a worker looks at the injector before its local queue,
+/// so a busy worker can't starve tasks woken from outside.
+const INJECTOR_INTERVAL: u32 = 61;
+
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+/// Woken while running; rescheduled once the current poll returns.
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+struct Stats {
+    polls: u64,
+    busy: Duration,
+    last_polled: Option<Instant>,
+}
+
+struct Task {
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    state: AtomicU8,
+    shared: Arc<Shared>,
+    id: u64,
+    location: &'static Location<'static>,
+    spawned: Instant,
+    wakes: AtomicU64,
+    stats: Mutex<Stats>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.state.load(Ordering::Acquire);
+
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) if next == SCHEDULED => return self.shared.schedule(Arc::clone(self)),
+                Ok(_) => return,
+                Err(current) => state = current,
+            }
+        }
+    }
+}
+
+impl Task {
+    fn run(self: Arc<Self>, worker: &Worker) {
+        self.state.store(RUNNING, Ordering::Release);
+
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = self.future.lock().unwrap();
+        let Some(pending) = future.as_mut() else {
+            return;
+        };
+
+        let started = Instant::now();
+        let poll = pending.as_mut().poll(&mut cx);
+
+        let mut stats = self.stats.lock().unwrap();
+        stats.polls += 1;
+        stats.busy += started.elapsed();
+        stats.last_polled = Some(Instant::now());
+        drop(stats);
+
+        if poll.is_ready() {
+            *future = None;
+            self.state.store(DONE, Ordering::Release);
+            return;
+        }
+        drop(future);
+
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // Woken during the poll.
+            self.state.store(SCHEDULED, Ordering::Release);
+            worker.push(self);
+        }
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        let state = match self.state.load(Ordering::Acquire) {
+            IDLE => TaskState::Idle,
+            SCHEDULED => TaskState::Scheduled,
+            RUNNING => TaskState::Running,
+            NOTIFIED => TaskState::Notified,
+            _ => TaskState::Done,
+        };
+        let stats = self.stats.lock().unwrap();
+
+        TaskSnapshot {
+            id: self.id,
+            location: self.location,
+            state,
+            polls: stats.polls,
+            wakes: self.wakes.load(Ordering::Relaxed),
+            busy: stats.busy,
+            age: self.spawned.elapsed(),
+            idle: stats.last_polled.map(|polled| polled.elapsed()),
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.shared.tasks.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Sleeping workers wait on `workers_available`; `sleepers` lets wakers skip
+/// the lock when nobody sleeps.
+struct Shared {
+    injector: Mutex<VecDeque<Arc<Task>>>,
+    locals: Vec<Mutex<VecDeque<Arc<Task>>>>,
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    workers_available: Condvar,
+    shutdown: AtomicBool,
+    /// Every task not dropped yet, for `dump`. Weak, since tasks point back
+    /// at `Shared`.
+    tasks: Mutex<HashMap<u64, Weak<Task>>>,
+    next_id: AtomicU64,
+}
+
+impl Shared {
+    fn schedule(self: &Arc<Self>, task: Arc<Task>) {
+        if self.shutdown.load(Ordering::Acquire) {
+            return;
+        }
+
+        let local = CURRENT.with(|current| match &*current.borrow() {
+            Some(Current {
+                shared,
+                worker: Some(index),
+            }) if Arc::ptr_eq(shared, self) => Some(*index),
+            _ => None,
+        });
+
+        match local {
+            Some(index) => self.locals[index].lock().unwrap().push_back(task),
+            None => self.injector.lock().unwrap().push_back(task),
+        }
+
+        self.notify_one();
+    }
+
+    fn notify_one(&self) {
+        fence(Ordering::SeqCst);
+
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            // Taking the lock ensures a worker that is about to sleep is
+            // already waiting when notified.
+            drop(self.sleep.lock().unwrap());
+            self.workers_available.notify_one();
+        }
+    }
+
+    fn has_work(&self) -> bool {
+        !self.injector.lock().unwrap().is_empty()
+            || self
+                .locals
+                .iter()
+                .any(|local| !local.lock().unwrap().is_empty())
+    }
+
+    fn spawn<F>(
+        self: &Arc<Self>,
+        future: F,
+        location: &'static Location<'static>,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (future, join) = task::harness(future);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            state: AtomicU8::new(SCHEDULED),
+            shared: Arc::clone(self),
+            id,
+            location,
+            spawned: Instant::now(),
+            wakes: AtomicU64::new(0),
+            stats: Mutex::new(Stats {
+                polls: 0,
+                busy: Duration::ZERO,
+                last_polled: None,
+            }),
+        });
+
+        self.tasks.lock().unwrap().insert(id, Arc::downgrade(&task));
+        self.schedule(task);
+
+        join
+    }
+}
+
+/// The runtime the current thread belongs to, and which worker it is.
+struct Current {
+    shared: Arc<Shared>,
+    worker: Option<usize>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Current>> = const { RefCell::new(None) };
+}
+
+/// Hand-rolled xorshift, good enough to pick steal victims.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+struct Worker {
+    shared: Arc<Shared>,
+    index: usize,
+    rng: XorShift,
+    ticks: u32,
+}
+
+impl Worker {
+    fn push(&self, task: Arc<Task>) {
+        self.shared.locals[self.index]
+            .lock()
+            .unwrap()
+            .push_back(task);
+        self.shared.notify_one();
+    }
+
+    fn run(mut self) {
+        while !self.shared.shutdown.load(Ordering::Acquire) {
+            match self.next_task() {
+                Some(task) => task.run(&self),
+                None => self.park(),
+            }
+        }
+    }
+
+    fn next_task(&mut self) -> Option<Arc<Task>> {
+        self.ticks = self.ticks.wrapping_add(1);
+
+        if self.ticks.is_multiple_of(INJECTOR_INTERVAL) {
+            if let Some(task) = self.shared.injector.lock().unwrap().pop_front() {
+                return Some(task);
+            }
+        }
+
+        let local = self.shared.locals[self.index].lock().unwrap().pop_front();
+
+        local
+            .or_else(|| self.shared.injector.lock().unwrap().pop_front())
+            .or_else(|| self.steal())
+    }
+
+    /// Takes the back half of a sibling's queue, starting at a random one.
+    fn steal(&mut self) -> Option<Arc<Task>> {
+        let workers = self.shared.locals.len();
+        let start = self.rng.next() as usize % workers;
+
+        for offset in 0..workers {
+            let victim = (start + offset) % workers;
+            if victim == self.index {
+                continue;
+            }
+
+            let mut queue = self.shared.locals[victim].lock().unwrap();
+            let len = queue.len();
+            if len == 0 {
+                continue;
+            }
+            let mut stolen = queue.split_off(len - len.div_ceil(2));
+            drop(queue);
+
+            let task = stolen.pop_front();
+            if !stolen.is_empty() {
+                self.shared.locals[self.index]
+                    .lock()
+                    .unwrap()
+                    .append(&mut stolen);
+                self.shared.notify_one();
+            }
+
+            return task;
+        }
+
+        None
+    }
+
+    fn park(&self) {
+        let guard = self.shared.sleep.lock().unwrap();
+        self.shared.sleepers.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        if !self.shared.has_work() && !self.shared.shutdown.load(Ordering::Acquire) {
+            let _unused = self.shared.workers_available.wait(guard).unwrap();
+        }
+
+        self.shared.sleepers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct ThreadWaker {
+    thread: Thread,
+    notified: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Handle
+///
+/// Spawns onto a `Runtime` from anywhere, including from its own tasks.
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Shared>,
+}
+
+impl Handle {
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.shared.spawn(future, Location::caller())
+    }
+}
+
+/// Runtime
+///
+/// Stops its workers when dropped. Tasks that have not completed by then are
+/// dropped with it, including idle ones still waiting on a timer or socket,
+/// and their `JoinHandle`s resolve as cancelled.
+pub struct Runtime {
+    handle: Handle,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    /// Starts `workers` worker threads (at least one).
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let shared = Arc::new(Shared {
+            injector: Mutex::new(VecDeque::new()),
+            locals: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            sleepers: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            workers_available: Condvar::default(),
+            shutdown: AtomicBool::new(false),
+            tasks: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        });
+
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+
+        let workers = (0..workers)
+            .map(|index| {
+                let worker = Worker {
+                    shared: Arc::clone(&shared),
+                    index,
+                    rng: XorShift::new(seed.wrapping_add(index as u64 * 0x9E37_79B9)),
+                    ticks: 0,
+                };
+
+                thread::Builder::new()
+                    .name(format!("async-worker-{index}"))
+                    .spawn(move || {
+                        enter(Arc::clone(&worker.shared), Some(index));
+                        worker.run();
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Self {
+            handle: Handle { shared },
+            workers,
+        }
+    }
+
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle.spawn(future)
+    }
+
+    /// Snapshots every task that has not been dropped yet.
+    pub fn dump(&self) -> Dump {
+        let shared = &self.handle.shared;
+
+        // Upgraded under the lock but dropped after it: dropping the last
+        // reference to a task takes the lock again.
+        let tasks: Vec<_> = shared
+            .tasks
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        let mut snapshots: Vec<_> = tasks.iter().map(|task| task.snapshot()).collect();
+        snapshots.sort_by_key(|snapshot| snapshot.id);
+        drop(tasks);
+
+        Dump { tasks: snapshots }
+    }
+
+    /// Runs `future` on the current thread while the workers run every spawned
+    /// task. `runtime::spawn` works inside `future`.
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        let previous = CURRENT.with(|current| {
+            current.borrow_mut().replace(Current {
+                shared: Arc::clone(&self.handle.shared),
+                worker: None,
+            })
+        });
+
+        let thread_waker = Arc::new(ThreadWaker {
+            thread: thread::current(),
+            notified: AtomicBool::new(false),
+        });
+        let waker = Waker::from(Arc::clone(&thread_waker));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = pin!(future);
+        let output = loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                break output;
+            }
+
+            while !thread_waker.notified.swap(false, Ordering::AcqRel) {
+                thread::park();
+            }
+        };
+
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+
+        output
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        let shared = &self.handle.shared;
+        shared.shutdown.store(true, Ordering::Release);
+
+        drop(shared.sleep.lock().unwrap());
+        shared.workers_available.notify_all();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+
+        // Queued tasks point back at `shared`.
+        shared.injector.lock().unwrap().clear();
+        for local in &shared.locals {
+            local.lock().unwrap().clear();
+        }
+
+        // Idle tasks are kept alive by whatever holds their waker, such as
+        // the timer or the reactor, so their futures are dropped here. Not
+        // under the registry lock: dropping a task takes it again.
+        let tasks: Vec<_> = shared
+            .tasks
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        for task in &tasks {
+            let future = task.future.lock().unwrap().take();
+            task.state.store(DONE, Ordering::Release);
+            drop(future);
+        }
+    }
+}
+
+fn enter(shared: Arc<Shared>, worker: Option<usize>) {
+    CURRENT.with(|current| *current.borrow_mut() = Some(Current { shared, worker }));
+}
+
+/// Spawns onto the runtime the current thread belongs to.
+///
+/// # Panics
+///
+/// Outside of a worker thread or `Runtime::block_on`.
+#[track_caller]
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let shared = CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|current| Arc::clone(&current.shared))
+            .expect("runtime::spawn called outside of a runtime")
+    });
+
+    shared.spawn(future, Location::caller())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{collections::HashSet, sync::mpsc, time::Duration};
+
+    use crate::{my_future::MyFuture, time::sleep};
+
+    #[test]
+    fn runs_every_spawned_task() {
+        let runtime = Runtime::new(4);
+        let (sender, receiver) = mpsc::channel();
+
+        for value in 0..1000 {
+            let sender = sender.clone();
+            runtime.spawn(async move {
+                sender.send(value).unwrap();
+            });
+        }
+        drop(sender);
+
+        let mut values: Vec<_> = receiver.iter().collect();
+        values.sort();
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn runs_futures_woken_from_foreign_threads() {
+        let runtime = Runtime::new(2);
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..8 {
+            let sender = sender.clone();
+            runtime.spawn(async move {
+                let result = MyFuture::new(Duration::from_millis(10)).await;
+                sender.send(result).unwrap();
+            });
+        }
+        drop(sender);
+
+        assert_eq!(receiver.iter().count(), 8);
+        assert_eq!(
+            runtime.block_on(MyFuture::new(Duration::from_millis(10))),
+            "We are done!!!"
+        );
+    }
+
+    #[test]
+    fn idle_workers_steal_from_busy_ones() {
+        let runtime = Runtime::new(4);
+        let (sender, receiver) = mpsc::channel();
+
+        // Spawned from a worker, so every task starts in that worker's queue.
+        runtime.spawn(async move {
+            for _ in 0..32 {
+                let sender = sender.clone();
+                spawn(async move {
+                    thread::sleep(Duration::from_millis(5));
+                    sender.send(thread::current().id()).unwrap();
+                });
+            }
+        });
+
+        let threads: HashSet<_> = receiver.iter().collect();
+        assert!(threads.len() > 1);
+    }
+
+    #[test]
+    fn dropping_the_runtime_cancels_idle_tasks() {
+        let runtime = Runtime::new(2);
+        let handle = runtime.spawn(sleep(Duration::from_secs(3600)));
+        runtime.block_on(sleep(Duration::from_millis(10)));
+
+        drop(runtime);
+
+        let error = crate::executor::Executor::new()
+            .block_on(handle)
+            .unwrap_err();
+        assert!(error.is_cancelled());
+    }
+
+    /// Dumps `runtime` until `ready` holds, as workers get to tasks in their
+    /// own time.
+    fn dump_when(runtime: &Runtime, ready: impl Fn(&Dump) -> bool) -> Dump {
+        let started = Instant::now();
+
+        loop {
+            let dump = runtime.dump();
+            if ready(&dump) {
+                return dump;
+            }
+
+            assert!(started.elapsed() < Duration::from_secs(10), "{dump}");
+            runtime.block_on(sleep(Duration::from_millis(1)));
+        }
+    }
+
+    #[test]
+    fn dump_describes_live_tasks() {
+        let runtime = Runtime::new(2);
+
+        let spawned_at = line!() + 1;
+        let handle = runtime.spawn(async {
+            for _ in 0..3 {
+                sleep(Duration::from_millis(1)).await;
+            }
+            MyFuture::new(Duration::from_secs(3600)).await
+        });
+
+        // Parked on `MyFuture` once it has been polled after every sleep.
+        let dump = dump_when(&runtime, |dump| {
+            dump.tasks
+                .iter()
+                .any(|task| task.state == TaskState::Idle && task.polls >= 4)
+        });
+        assert_eq!(dump.tasks.len(), 1);
+
+        let task = &dump.tasks[0];
+        assert!(task.wakes >= 3);
+        assert_eq!(
+            (task.location.file(), task.location.line()),
+            (file!(), spawned_at)
+        );
+        assert!(task.idle.is_some_and(|idle| idle <= task.age));
+        assert!(dump
+            .to_string()
+            .contains(&format!("polls={} wakes={}", task.polls, task.wakes)));
+
+        handle.abort();
+        assert!(runtime.block_on(handle).unwrap_err().is_cancelled());
+        dump_when(&runtime, |dump| dump.tasks.is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Deterministic simulation runtime for tests.
+//!
+//! Everything runs on the calling thread. Whenever several tasks are ready the
+//! next one is picked by a random number generator seeded by the caller, and
+//! when none is ready the virtual clock jumps straight to the next timer. An
+//! hour of `sleep` takes no time at all, and the same seed always produces the
+//! same interleaving, so a failure found with one seed can be replayed.
+//!
+//! Timers must be created while the `Sim` is alive; sockets are not
+//! simulated, and tasks waiting on them look deadlocked.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::Future,
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    runtime::XorShift,
+    task::{self, JoinHandle},
+    time,
+};
+
+/// Id the future passed to `block_on` is scheduled under.
+const MAIN: usize = usize::MAX;
+
+struct TaskWaker {
+    id: usize,
+    scheduled: AtomicBool,
+    ready: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.ready.lock().unwrap().push(self.id);
+        }
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    handle: Arc<TaskWaker>,
+    waker: Waker,
+}
+
+struct Shared {
+    seed: u64,
+    rng: RefCell<XorShift>,
+    /// Unordered: the next task is drawn from it at random.
+    ready: Arc<Mutex<Vec<usize>>>,
+    tasks: RefCell<HashMap<usize, Task>>,
+    next_id: Cell<usize>,
+    timer: time::Handle,
+    started: Instant,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // Tasks may still hold timers, which need the handle to cancel.
+        self.tasks.borrow_mut().clear();
+        self.timer.leave();
+    }
+}
+
+/// Sim
+///
+/// Cheap to clone, like `Executor`. While any clone is alive, `sleep` and
+/// every other timer on this thread run on the simulation's virtual clock.
+#[derive(Clone)]
+pub struct Sim {
+    shared: Rc<Shared>,
+}
+
+impl Sim {
+    pub fn new(seed: u64) -> Self {
+        let timer = time::Handle::simulated();
+        timer.enter();
+
+        Self {
+            shared: Rc::new(Shared {
+                seed,
+                // Spread the seed so neighbouring seeds diverge right away.
+                rng: RefCell::new(XorShift::new(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15))),
+                ready: Arc::new(Mutex::new(Vec::new())),
+                tasks: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                started: timer.now(),
+                timer,
+            }),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.shared.seed
+    }
+
+    /// Virtual time since the simulation started.
+    pub fn elapsed(&self) -> Duration {
+        self.shared.timer.now() - self.shared.started
+    }
+
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let (future, join) = task::harness(future);
+
+        let id = self.shared.next_id.get();
+        self.shared.next_id.set(id + 1);
+
+        let handle = self.task_waker(id);
+        let waker = Waker::from(Arc::clone(&handle));
+        waker.wake_by_ref();
+
+        let task = Task {
+            future: Box::pin(future),
+            handle,
+            waker,
+        };
+        self.shared.tasks.borrow_mut().insert(id, task);
+
+        join
+    }
+
+    /// Runs `future` to completion together with every spawned task.
+    ///
+    /// # Panics
+    ///
+    /// If `future` can't complete: nothing is ready and no timer is pending.
+    /// The seed is printed whenever `block_on` panics, to replay the run.
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        let _report = ReportSeed(self.shared.seed);
+
+        let mut future = pin!(future);
+        let main = self.task_waker(MAIN);
+        let main_waker = Waker::from(Arc::clone(&main));
+        main_waker.wake_by_ref();
+
+        loop {
+            match self.next_ready() {
+                Some(MAIN) => {
+                    main.scheduled.store(false, Ordering::Release);
+
+                    let mut cx = Context::from_waker(&main_waker);
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        return output;
+                    }
+                }
+                Some(id) => self.run(id),
+                None => {
+                    if !self.shared.timer.advance() {
+                        panic!("sim deadlocked: no task is ready and no timer is pending");
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_ready(&self) -> Option<usize> {
+        let mut ready = self.shared.ready.lock().unwrap();
+        if ready.is_empty() {
+            return None;
+        }
+        let index = self.shared.rng.borrow_mut().next() as usize % ready.len();
+
+        Some(ready.swap_remove(index))
+    }
+
+    fn run(&self, id: usize) {
+        // Taken out of the map while polled, so it may spawn.
+        let Some(mut task) = self.shared.tasks.borrow_mut().remove(&id) else {
+            return;
+        };
+        task.handle.scheduled.store(false, Ordering::Release);
+
+        let mut cx = Context::from_waker(&task.waker);
+        if task.future.as_mut().poll(&mut cx).is_pending() {
+            self.shared.tasks.borrow_mut().insert(id, task);
+        }
+    }
+
+    fn task_waker(&self, id: usize) -> Arc<TaskWaker> {
+        Arc::new(TaskWaker {
+            id,
+            scheduled: AtomicBool::new(false),
+            ready: Arc::clone(&self.shared.ready),
+        })
+    }
+}
+
+struct ReportSeed(u64);
+
+impl Drop for ReportSeed {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            eprintln!(
+                "sim failed with seed {}; Sim::new({}) replays it",
+                self.0, self.0
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        my_future::MyFuture,
+        sync::Notify,
+        time::{sleep, timeout},
+    };
+
+    #[test]
+    fn timers_complete_without_waiting() {
+        let sim = Sim::new(1);
+        let started = Instant::now();
+
+        let result = sim.block_on(MyFuture::new(Duration::from_secs(3600)));
+
+        assert_eq!(result, "We are done!!!");
+        assert_eq!(sim.elapsed(), Duration::from_secs(3600));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn timeouts_follow_the_virtual_clock() {
+        let sim = Sim::new(1);
+
+        let result = sim.block_on(timeout(
+            Duration::from_secs(1),
+            MyFuture::new(Duration::from_secs(3600)),
+        ));
+
+        assert!(result.is_err());
+        assert_eq!(sim.elapsed(), Duration::from_secs(1));
+    }
+
+    fn interleaving(seed: u64) -> Vec<(usize, usize)> {
+        let sim = Sim::new(seed);
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let handles: Vec<_> = (0..5)
+            .map(|task| {
+                let log = Rc::clone(&log);
+                sim.spawn(async move {
+                    for step in 0..3 {
+                        log.borrow_mut().push((task, step));
+                        sleep(Duration::from_millis(10)).await;
+                    }
+                })
+            })
+            .collect();
+
+        sim.block_on(async {
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        });
+        assert_eq!(sim.elapsed(), Duration::from_millis(30));
+
+        log.take()
+    }
+
+    #[test]
+    fn a_seed_replays_the_same_interleaving() {
+        assert_eq!(interleaving(7), interleaving(7));
+
+        let distinct: std::collections::HashSet<_> = (0..20).map(interleaving).collect();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "sim deadlocked")]
+    fn reports_deadlocks() {
+        let sim = Sim::new(1);
+        let notify = Notify::new();
+
+        sim.block_on(notify.notified());
+    }
+
+    #[test]
+    fn restores_the_real_clock_when_dropped() {
+        drop(Sim::new(1));
+
+        let started = Instant::now();
+        crate::executor::Executor::new().block_on(sleep(Duration::from_millis(5)));
+
+        assert!(started.elapsed() >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn sims_may_be_dropped_in_any_order() {
+        let first = Sim::new(1);
+        let second = Sim::new(2);
+        drop(first);
+
+        second.block_on(sleep(Duration::from_secs(3600)));
+        assert_eq!(second.elapsed(), Duration::from_secs(3600));
+        drop(second);
+
+        let started = Instant::now();
+        crate::executor::Executor::new().block_on(sleep(Duration::from_millis(5)));
+
+        assert!(started.elapsed() >= Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    future::poll_fn,
+    sync::Mutex,
+    task::{Poll, Waker},
+};
+
+struct State {
+    arrived: usize,
+    /// Bumped every time the barrier releases its waiters.
+    generation: u64,
+    waiters: Vec<Waker>,
+}
+
+/// Barrier
+///
+/// Holds tasks until `parties` of them wait on it, then releases them all and
+/// starts over.
+pub struct Barrier {
+    parties: usize,
+    state: Mutex<State>,
+}
+
+/// BarrierWaitResult
+///
+/// Exactly one task per generation is the leader.
+#[derive(Debug)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
+
+impl Barrier {
+    pub fn new(parties: usize) -> Self {
+        Self {
+            parties: parties.max(1),
+            state: Mutex::new(State {
+                arrived: 0,
+                generation: 0,
+                waiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// Arriving counts immediately: a task that stops waiting (the future is
+    /// dropped) still counts towards the current generation.
+    pub async fn wait(&self) -> BarrierWaitResult {
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.arrived += 1;
+
+            if state.arrived == self.parties {
+                state.arrived = 0;
+                state.generation += 1;
+                let waiters = std::mem::take(&mut state.waiters);
+                drop(state);
+
+                waiters.into_iter().for_each(Waker::wake);
+                return BarrierWaitResult { leader: true };
+            }
+
+            state.generation
+        };
+
+        let mut registered: Option<usize> = None;
+        poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.generation != generation {
+                return Poll::Ready(());
+            }
+
+            match registered {
+                Some(index) if state.waiters[index].will_wake(cx.waker()) => {}
+                Some(index) => state.waiters[index].clone_from(cx.waker()),
+                None => {
+                    registered = Some(state.waiters.len());
+                    state.waiters.push(cx.waker().clone());
+                }
+            }
+
+            Poll::Pending
+        })
+        .await;
+
+        BarrierWaitResult { leader: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn releases_everyone_once_full() {
+        let runtime = Runtime::new(4);
+        let barrier = Arc::new(Barrier::new(10));
+        let arrived = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let barrier = Arc::clone(&barrier);
+                let arrived = Arc::clone(&arrived);
+                runtime.spawn(async move {
+                    arrived.fetch_add(1, Ordering::SeqCst);
+                    let result = barrier.wait().await;
+                    // Nobody gets past before everyone arrived.
+                    assert_eq!(arrived.load(Ordering::SeqCst), 10);
+                    result.is_leader()
+                })
+            })
+            .collect();
+
+        let leaders = runtime.block_on(async {
+            let mut leaders = 0;
+            for handle in handles {
+                leaders += usize::from(handle.await.unwrap());
+            }
+            leaders
+        });
+
+        assert_eq!(leaders, 1);
+    }
+}
@@ -0,0 +1,17 @@
+//! Synchronization primitives for tasks.
+//!
+//! Unlike their `std::sync` counterparts these never block the thread: a task
+//! that has to wait is queued with its `Waker` and yields, so the executor can
+//! keep running other tasks. Waiters are served first in, first out.
+
+mod barrier;
+mod mutex;
+mod notify;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
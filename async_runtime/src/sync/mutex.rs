@@ -0,0 +1,143 @@
+use std::{
+    cell::UnsafeCell,
+    fmt::{self, Debug},
+    ops::{Deref, DerefMut},
+};
+
+use super::semaphore::{Acquire, Semaphore};
+
+/// Mutex
+///
+/// A semaphore with a single permit around the value, so lockers get it in the
+/// order they asked. The guard may be held across `.await`.
+pub struct Mutex<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(1),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        Acquire::new(&self.semaphore, 1).await;
+
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.semaphore
+            .try_acquire()
+            .map(|permit| permit.forget())
+            .map(|()| MutexGuard { mutex: self })
+    }
+
+    /// No locking needed: `&mut self` proves nobody else holds a guard.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("locked", &(self.semaphore.available_permits() == 0))
+            .finish_non_exhaustive()
+    }
+}
+
+/// MutexGuard
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.semaphore.release(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::Arc, time::Duration};
+
+    use crate::{runtime::Runtime, time::sleep};
+
+    #[test]
+    fn guards_exclude_each_other_across_awaits() {
+        let runtime = Runtime::new(4);
+        let counter = Arc::new(Mutex::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                runtime.spawn(async move {
+                    for _ in 0..100 {
+                        let mut value = counter.lock().await;
+                        let read = *value;
+                        if read % 25 == 0 {
+                            sleep(Duration::from_millis(1)).await;
+                        }
+                        *value = read + 1;
+                    }
+                })
+            })
+            .collect();
+
+        runtime.block_on(async {
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        });
+
+        assert_eq!(*runtime.block_on(counter.lock()), 800);
+    }
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let mutex = Mutex::new(());
+
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+struct State {
+    /// Stored by `notify_one` when nobody waits; taken by the next `notified`.
+    permit: bool,
+    waiters: VecDeque<(u64, Waker)>,
+    /// Woken by `notify_one` but not polled yet. If such a waiter is dropped,
+    /// its notification is passed on instead of being lost.
+    handed_over: HashSet<u64>,
+    next_id: u64,
+}
+
+impl State {
+    fn notify_one(&mut self) -> Option<Waker> {
+        match self.waiters.pop_front() {
+            Some((id, waker)) => {
+                self.handed_over.insert(id);
+                Some(waker)
+            }
+            None => {
+                self.permit = true;
+                None
+            }
+        }
+    }
+}
+
+/// Notify
+///
+/// Wakes tasks waiting on `notified()`, oldest first.
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                permit: false,
+                waiters: VecDeque::new(),
+                handed_over: HashSet::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    /// Completes once notified. Registration happens on the first poll.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            id: None,
+            done: false,
+        }
+    }
+
+    /// Wakes the oldest waiter, or lets the next `notified()` complete
+    /// immediately if nobody waits. Permits don't accumulate.
+    pub fn notify_one(&self) {
+        let waker = self.state.lock().unwrap().notify_one();
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Wakes everyone currently waiting, without storing a permit.
+    pub fn notify_waiters(&self) {
+        let mut state = self.state.lock().unwrap();
+        let waiters: Vec<_> = state.waiters.drain(..).collect();
+        drop(state);
+
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Notified
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    id: Option<u64>,
+    done: bool,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.notify.state.lock().unwrap();
+
+        let Some(id) = this.id else {
+            if state.permit {
+                state.permit = false;
+                this.done = true;
+                return Poll::Ready(());
+            }
+
+            let id = state.next_id;
+            state.next_id += 1;
+            state.waiters.push_back((id, cx.waker().clone()));
+            this.id = Some(id);
+
+            return Poll::Pending;
+        };
+
+        match state.waiters.iter_mut().find(|(waiter, _)| *waiter == id) {
+            Some((_, waker)) => {
+                if !waker.will_wake(cx.waker()) {
+                    waker.clone_from(cx.waker());
+                }
+                Poll::Pending
+            }
+            None => {
+                state.handed_over.remove(&id);
+                this.done = true;
+                Poll::Ready(())
+            }
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id.filter(|_| !self.done) else {
+            return;
+        };
+
+        let mut state = self.notify.state.lock().unwrap();
+        state.waiters.retain(|(waiter, _)| *waiter != id);
+
+        let waker = if state.handed_over.remove(&id) {
+            state.notify_one()
+        } else {
+            None
+        };
+        drop(state);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::{mpsc, Arc},
+        time::Duration,
+    };
+
+    use crate::{executor::Executor, runtime::Runtime, time::sleep};
+
+    #[test]
+    fn stored_permit_completes_the_next_waiter() {
+        let executor = Executor::new();
+        let notify = Notify::new();
+
+        notify.notify_one();
+        notify.notify_one();
+        executor.block_on(notify.notified());
+
+        assert!(executor
+            .block_on(async {
+                let mut notified = Box::pin(notify.notified());
+                std::future::poll_fn(|cx| Poll::Ready(notified.as_mut().poll(cx))).await
+            })
+            .is_pending());
+    }
+
+    #[test]
+    fn notify_one_wakes_waiters_in_order() {
+        let runtime = Runtime::new(2);
+        let notify = Arc::new(Notify::new());
+        let (sender, receiver) = mpsc::channel();
+
+        for waiter in 0..3 {
+            let notify = Arc::clone(&notify);
+            let sender = sender.clone();
+            runtime.spawn(async move {
+                notify.notified().await;
+                sender.send(waiter).unwrap();
+            });
+            // Each waiter is registered before the next one is spawned.
+            runtime.block_on(sleep(Duration::from_millis(5)));
+        }
+
+        for _ in 0..3 {
+            notify.notify_one();
+            runtime.block_on(sleep(Duration::from_millis(5)));
+        }
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn dropped_waiter_passes_its_notification_on() {
+        let executor = Executor::new();
+        let notify = Notify::new();
+
+        executor.block_on(async {
+            let mut first = Box::pin(notify.notified());
+            let mut second = Box::pin(notify.notified());
+            std::future::poll_fn(|cx| {
+                assert!(first.as_mut().poll(cx).is_pending());
+                assert!(second.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+
+            notify.notify_one();
+            drop(first);
+            second.await;
+        });
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+};
+
+use super::semaphore::{Acquire, Semaphore};
+
+/// Readers take one permit, a writer takes all of them.
+const MAX_READERS: usize = u32::MAX as usize >> 3;
+
+/// RwLock
+///
+/// Built on a FIFO semaphore: a queued writer holds back readers that arrive
+/// after it, so writers can't be starved by a steady stream of readers.
+pub struct RwLock<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            semaphore: Semaphore::new(MAX_READERS),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        Acquire::new(&self.semaphore, 1).await;
+
+        RwLockReadGuard { lock: self }
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        Acquire::new(&self.semaphore, MAX_READERS).await;
+
+        RwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.semaphore
+            .try_acquire()
+            .map(|permit| permit.forget())
+            .map(|()| RwLockReadGuard { lock: self })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.semaphore
+            .try_acquire_many(MAX_READERS)
+            .map(|permit| permit.forget())
+            .map(|()| RwLockWriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+/// RwLockReadGuard
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Send for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.release(1);
+    }
+}
+
+/// RwLockWriteGuard
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Send for RwLockWriteGuard<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.semaphore.release(MAX_READERS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use crate::{executor::Executor, time::sleep};
+
+    #[test]
+    fn readers_share_writers_exclude() {
+        let lock = RwLock::new(1);
+
+        let first = lock.try_read().unwrap();
+        let second = lock.try_read().unwrap();
+        assert_eq!(*first + *second, 2);
+        assert!(lock.try_write().is_none());
+
+        drop((first, second));
+        let mut writer = lock.try_write().unwrap();
+        *writer = 2;
+        assert!(lock.try_read().is_none());
+
+        drop(writer);
+        assert_eq!(lock.into_inner(), 2);
+    }
+
+    #[test]
+    fn queued_writer_goes_before_later_readers() {
+        let executor = Executor::new();
+        let lock = Rc::new(RwLock::new(Vec::new()));
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let held = lock.try_read().unwrap();
+
+        let writer = Rc::clone(&lock);
+        executor.spawn(async move { writer.write().await.push("written") });
+
+        let reader = Rc::clone(&lock);
+        let reader_seen = Rc::clone(&seen);
+        executor.spawn(async move {
+            sleep(Duration::from_millis(5)).await;
+            let value = reader.read().await;
+            reader_seen.borrow_mut().extend(value.iter().copied());
+        });
+
+        executor.block_on(async {
+            sleep(Duration::from_millis(10)).await;
+            drop(held);
+            sleep(Duration::from_millis(5)).await;
+        });
+
+        assert_eq!(*seen.borrow(), vec!["written"]);
+    }
+}
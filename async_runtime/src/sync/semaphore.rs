@@ -0,0 +1,275 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+struct Waiter {
+    id: u64,
+    permits: usize,
+    waker: Waker,
+}
+
+/// Waiters are served strictly in arrival order: a large request at the front
+/// holds back smaller ones behind it, so it can't be starved.
+struct State {
+    permits: usize,
+    waiters: VecDeque<Waiter>,
+    next_id: u64,
+}
+
+impl State {
+    /// Hands free permits to the front of the queue. A waiter that is granted
+    /// its permits is removed, which is how its `Acquire` learns about it.
+    fn grant(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+
+        while let Some(front) = self.waiters.front() {
+            if front.permits > self.permits {
+                break;
+            }
+            self.permits -= front.permits;
+            wakers.extend(self.waiters.pop_front().map(|waiter| waiter.waker));
+        }
+
+        wakers
+    }
+}
+
+/// Semaphore
+///
+/// Hands out permits in FIFO order. Works with any executor: waiting tasks
+/// are only ever woken through their `Waker`.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    pub fn add_permits(&self, permits: usize) {
+        self.release(permits);
+    }
+
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1).await
+    }
+
+    pub async fn acquire_many(&self, permits: usize) -> SemaphorePermit<'_> {
+        Acquire::new(self, permits).await;
+
+        SemaphorePermit {
+            semaphore: self,
+            permits,
+        }
+    }
+
+    /// Fails if tasks are already waiting, even when enough permits are free.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, permits: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.waiters.is_empty() || state.permits < permits {
+            return None;
+        }
+        state.permits -= permits;
+
+        Some(SemaphorePermit {
+            semaphore: self,
+            permits,
+        })
+    }
+
+    pub(super) fn release(&self, permits: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.permits += permits;
+        let wakers = state.grant();
+        drop(state);
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Waits in the queue until `permits` have been granted to it.
+pub(super) struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    /// Set while queued.
+    id: Option<u64>,
+    done: bool,
+}
+
+impl<'a> Acquire<'a> {
+    pub(super) fn new(semaphore: &'a Semaphore, permits: usize) -> Self {
+        Self {
+            semaphore,
+            permits,
+            id: None,
+            done: false,
+        }
+    }
+}
+
+impl Future for Acquire<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.semaphore.state.lock().unwrap();
+
+        let Some(id) = this.id else {
+            if state.waiters.is_empty() && state.permits >= this.permits {
+                state.permits -= this.permits;
+                this.done = true;
+                return Poll::Ready(());
+            }
+
+            let id = state.next_id;
+            state.next_id += 1;
+            state.waiters.push_back(Waiter {
+                id,
+                permits: this.permits,
+                waker: cx.waker().clone(),
+            });
+            this.id = Some(id);
+
+            return Poll::Pending;
+        };
+
+        match state.waiters.iter_mut().find(|waiter| waiter.id == id) {
+            Some(waiter) => {
+                if !waiter.waker.will_wake(cx.waker()) {
+                    waiter.waker.clone_from(cx.waker());
+                }
+                Poll::Pending
+            }
+            None => {
+                this.done = true;
+                Poll::Ready(())
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let Some(id) = self.id.filter(|_| !self.done) else {
+            return;
+        };
+
+        let mut state = self.semaphore.state.lock().unwrap();
+        match state.waiters.iter().position(|waiter| waiter.id == id) {
+            // Leaving the front may unblock the waiters behind.
+            Some(index) => {
+                state.waiters.remove(index);
+            }
+            // Granted, but nobody is going to use the permits.
+            None => state.permits += self.permits,
+        }
+        let wakers = state.grant();
+        drop(state);
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// SemaphorePermit
+///
+/// Returns its permits when dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permits out of the semaphore for good.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use crate::{executor::Executor, time::sleep};
+
+    #[test]
+    fn waiters_are_served_in_order() {
+        let executor = Executor::new();
+        let semaphore = Rc::new(Semaphore::new(0));
+        let order = Rc::new(RefCell::new(Vec::new()));
+
+        // The large request arrives first and must not be overtaken.
+        for (name, permits) in [("big", 3), ("small", 1)] {
+            let semaphore = Rc::clone(&semaphore);
+            let order = Rc::clone(&order);
+            executor.spawn(async move {
+                let _permit = semaphore.acquire_many(permits).await;
+                order.borrow_mut().push(name);
+            });
+        }
+
+        executor.block_on(async {
+            sleep(Duration::from_millis(5)).await;
+            semaphore.add_permits(1);
+            sleep(Duration::from_millis(5)).await;
+            assert!(order.borrow().is_empty());
+
+            semaphore.add_permits(2);
+            sleep(Duration::from_millis(5)).await;
+        });
+
+        assert_eq!(*order.borrow(), vec!["big", "small"]);
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+
+    #[test]
+    fn dropped_waiters_leave_the_queue() {
+        let executor = Executor::new();
+        let semaphore = Semaphore::new(1);
+
+        executor.block_on(async {
+            let held = semaphore.acquire().await;
+
+            let mut waiting = Box::pin(semaphore.acquire_many(2));
+            std::future::poll_fn(|cx| {
+                assert!(waiting.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            assert!(semaphore.try_acquire().is_none());
+
+            drop(waiting);
+            drop(held);
+            assert!(semaphore.try_acquire().is_some());
+        });
+    }
+}
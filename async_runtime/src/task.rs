@@ -0,0 +1,388 @@
+//! Join handles shared by every executor, and task snapshots for diagnostics.
+//!
+//! A spawned future is wrapped in a `Harness` before the executor sees it. The
+//! harness catches panics, stores the output for the `JoinHandle`, and drops
+//! the future as soon as it is polled after `abort()`.
+
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{self, Debug, Display},
+    future::Future,
+    panic::{self, AssertUnwindSafe, Location},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+enum Repr {
+    Cancelled,
+    Panicked(Box<dyn Any + Send>),
+}
+
+/// JoinError
+///
+/// Why a task did not produce its output.
+pub struct JoinError {
+    repr: Repr,
+}
+
+impl JoinError {
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.repr, Repr::Cancelled)
+    }
+
+    pub fn is_panic(&self) -> bool {
+        matches!(self.repr, Repr::Panicked(_))
+    }
+
+    /// The panic payload, to resume it with `std::panic::resume_unwind`.
+    ///
+    /// # Panics
+    ///
+    /// If the task was cancelled.
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        match self.repr {
+            Repr::Panicked(payload) => payload,
+            Repr::Cancelled => panic!("task was cancelled, not panicked"),
+        }
+    }
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.repr {
+            Repr::Cancelled => f.write_str("task was cancelled"),
+            Repr::Panicked(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str));
+
+                match message {
+                    Some(message) => write!(f, "task panicked: {message}"),
+                    None => f.write_str("task panicked"),
+                }
+            }
+        }
+    }
+}
+
+impl Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for JoinError {}
+
+struct State<T> {
+    output: Option<Result<T, JoinError>>,
+    finished: bool,
+    aborted: bool,
+    /// Waker of the task itself, so `abort` can get it polled.
+    task: Option<Waker>,
+    /// Waker of whoever awaits the `JoinHandle`.
+    join: Option<Waker>,
+}
+
+impl<T> State<T> {
+    fn finish(&mut self, output: Result<T, JoinError>) -> Option<Waker> {
+        self.output = Some(output);
+        self.finished = true;
+
+        self.join.take()
+    }
+}
+
+/// JoinHandle
+///
+/// Awaits the output of a spawned task. Dropping it detaches the task, which
+/// keeps running.
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Cancels the task. Its future is dropped the next time the executor
+    /// gets to it, and the handle resolves to a cancelled `JoinError` unless
+    /// the task already finished.
+    pub fn abort(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        state.aborted = true;
+
+        let task = state.task.take();
+        drop(state);
+
+        if let Some(task) = task {
+            task.wake();
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+        assert!(!state.finished, "JoinHandle polled after completion");
+
+        match &mut state.join {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+/// What executors actually run in place of a spawned future.
+pub(crate) struct Harness<F: Future> {
+    future: Option<Pin<Box<F>>>,
+    state: Arc<Mutex<State<F::Output>>>,
+}
+
+impl<F: Future> Future for Harness<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let mut state = this.state.lock().unwrap();
+        if state.aborted {
+            drop(state);
+
+            // Dropped before the handle learns about it, so anything the
+            // future held is released by the time the handle resolves.
+            this.future = None;
+
+            let join = this.state.lock().unwrap().finish(Err(JoinError {
+                repr: Repr::Cancelled,
+            }));
+            join.into_iter().for_each(Waker::wake);
+            return Poll::Ready(());
+        }
+
+        match &mut state.task {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+        drop(state);
+
+        let Some(future) = this.future.as_mut() else {
+            return Poll::Ready(());
+        };
+
+        let output = match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => return Poll::Pending,
+            Ok(Poll::Ready(output)) => Ok(output),
+            Err(payload) => Err(JoinError {
+                repr: Repr::Panicked(payload),
+            }),
+        };
+        this.future = None;
+
+        let mut state = this.state.lock().unwrap();
+        state.task = None;
+        let join = state.finish(output);
+        drop(state);
+
+        join.into_iter().for_each(Waker::wake);
+        Poll::Ready(())
+    }
+}
+
+impl<F: Future> Drop for Harness<F> {
+    /// Executors drop unfinished tasks when they shut down.
+    fn drop(&mut self) {
+        self.future = None;
+
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.finished {
+            return;
+        }
+        let join = state.finish(Err(JoinError {
+            repr: Repr::Cancelled,
+        }));
+        drop(state);
+
+        join.into_iter().for_each(Waker::wake);
+    }
+}
+
+pub(crate) fn harness<F: Future>(future: F) -> (Harness<F>, JoinHandle<F::Output>) {
+    let state = Arc::new(Mutex::new(State {
+        output: None,
+        finished: false,
+        aborted: false,
+        task: None,
+        join: None,
+    }));
+
+    (
+        Harness {
+            future: Some(Box::pin(future)),
+            state: Arc::clone(&state),
+        },
+        JoinHandle { state },
+    )
+}
+
+/// TaskState
+///
+/// Where a task is in its life cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Waiting to be woken.
+    Idle,
+    /// Queued on a worker or the injector.
+    Scheduled,
+    Running,
+    /// Woken while running; queued again once the current poll returns.
+    Notified,
+    Done,
+}
+
+/// TaskSnapshot
+///
+/// What a task has been up to, as of the moment it was taken.
+#[derive(Debug, Clone)]
+pub struct TaskSnapshot {
+    pub id: u64,
+    /// Where `spawn` was called.
+    pub location: &'static Location<'static>,
+    pub state: TaskState,
+    pub polls: u64,
+    pub wakes: u64,
+    /// Total time spent inside `poll`.
+    pub busy: Duration,
+    /// Time since the task was spawned.
+    pub age: Duration,
+    /// Time since the last poll returned, `None` before the first one.
+    pub idle: Option<Duration>,
+}
+
+/// Dump
+///
+/// Every task alive in a runtime, ordered by id. A task that is `Idle` with
+/// a large `idle` is waiting on something that may never wake it.
+#[derive(Debug, Clone, Default)]
+pub struct Dump {
+    pub tasks: Vec<TaskSnapshot>,
+}
+
+impl Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} tasks", self.tasks.len())?;
+
+        for task in &self.tasks {
+            write!(
+                f,
+                "  #{} {:?} polls={} wakes={} busy={:?} age={:?}",
+                task.id, task.state, task.polls, task.wakes, task.busy, task.age
+            )?;
+            if let Some(idle) = task.idle {
+                write!(f, " idle={idle:?}")?;
+            }
+            writeln!(f, " spawned at {}", task.location)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    use crate::{executor::Executor, my_future::MyFuture, runtime::Runtime, time::sleep};
+
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn handle_yields_output() {
+        let executor = Executor::new();
+        let handle = executor.spawn(async { 6 * 7 });
+
+        assert_eq!(executor.block_on(handle).unwrap(), 42);
+    }
+
+    #[test]
+    fn handle_reports_panics() {
+        let runtime = Runtime::new(2);
+        let handle = runtime.spawn(async { panic!("boom") });
+
+        let error = runtime.block_on(handle).unwrap_err();
+
+        assert!(error.is_panic());
+        assert_eq!(error.to_string(), "task panicked: boom");
+        // The worker survived the panic.
+        assert_eq!(runtime.block_on(runtime.spawn(async { 1 })).unwrap(), 1);
+    }
+
+    #[test]
+    fn abort_drops_the_future_promptly() {
+        let runtime = Runtime::new(2);
+        let dropped = Arc::new(AtomicBool::new(false));
+
+        let flag = DropFlag(Arc::clone(&dropped));
+        let handle = runtime.spawn(async move {
+            let _flag = flag;
+            MyFuture::new(Duration::from_secs(3600)).await
+        });
+
+        runtime.block_on(sleep(Duration::from_millis(10)));
+        handle.abort();
+        let error = runtime.block_on(handle).unwrap_err();
+
+        assert!(error.is_cancelled());
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn abort_after_completion_keeps_output() {
+        let executor = Executor::new();
+        let handle = executor.spawn(async { "done" });
+
+        executor.block_on(sleep(Duration::from_millis(1)));
+        handle.abort();
+
+        assert_eq!(executor.block_on(handle).unwrap(), "done");
+    }
+
+    #[test]
+    fn dropping_the_executor_cancels_pending_tasks() {
+        let executor = Executor::new();
+        let handle = executor.spawn(sleep(Duration::from_secs(3600)));
+        executor.block_on(sleep(Duration::from_millis(10)));
+        drop(executor);
+
+        let error = Executor::new().block_on(handle).unwrap_err();
+        assert!(error.is_cancelled());
+    }
+}
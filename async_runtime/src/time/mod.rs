@@ -0,0 +1,465 @@
+//! Timers for any executor.
+//!
+//! Every `Sleep` registers its waker in one timing wheel, driven by a single
+//! background thread that sleeps until the next deadline. Ten thousand pending
+//! timers cost ten thousand wheel entries, not ten thousand threads.
+//!
+//! Inside a `sim::Sim` timers use a wheel of their own with a virtual clock
+//! instead, which only moves when the simulation jumps to the next deadline.
+
+mod timeout;
+mod wheel;
+
+use std::{
+    cell::RefCell,
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub use timeout::{timeout, timeout_at, Elapsed, FutureExt, Timeout};
+
+use wheel::{Key, Wheel};
+
+/// Resolution of the wheel.
+const TICK: Duration = Duration::from_millis(1);
+
+enum Clock {
+    Real,
+    /// Moved forward by `Handle::advance`, never by itself.
+    Virtual(Mutex<Instant>),
+}
+
+pub(crate) struct Timer {
+    wheel: Mutex<Wheel<Waker>>,
+    start: Instant,
+    changed: Condvar,
+    clock: Clock,
+}
+
+impl Timer {
+    /// A virtual clock starts at `start`, so whole ticks land exactly on the
+    /// deadlines they stand for.
+    fn new(simulated: bool) -> Self {
+        let start = Instant::now();
+
+        Self {
+            wheel: Mutex::new(Wheel::new()),
+            start,
+            changed: Condvar::default(),
+            clock: if simulated {
+                Clock::Virtual(Mutex::new(start))
+            } else {
+                Clock::Real
+            },
+        }
+    }
+
+    fn now(&self) -> Instant {
+        match &self.clock {
+            Clock::Real => Instant::now(),
+            Clock::Virtual(now) => *now.lock().unwrap(),
+        }
+    }
+
+    /// First tick at or after `instant`, so nothing fires early.
+    fn tick_at(&self, instant: Instant) -> u64 {
+        let since_start = instant.saturating_duration_since(self.start);
+
+        since_start.as_nanos().div_ceil(TICK.as_nanos()) as u64
+    }
+
+    /// Last tick reached at `instant`.
+    fn elapsed_at(&self, instant: Instant) -> u64 {
+        let since_start = instant.saturating_duration_since(self.start);
+
+        (since_start.as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    fn instant_at(&self, tick: u64) -> Instant {
+        let since_start = tick.saturating_mul(TICK.as_nanos() as u64);
+
+        self.start + Duration::from_nanos(since_start)
+    }
+
+    fn register(&self, deadline: Instant, waker: &Waker, key: &mut Option<Key>) {
+        let mut wheel = self.wheel.lock().unwrap();
+
+        if let Some(stored) = key.and_then(|key| wheel.get_mut(key)) {
+            if !stored.will_wake(waker) {
+                stored.clone_from(waker);
+            }
+            return;
+        }
+
+        let when = self.tick_at(deadline);
+        let earlier = wheel.next_expiration().is_none_or(|next| when < next);
+
+        match wheel.insert(when, waker.clone()) {
+            Ok(inserted) => {
+                *key = Some(inserted);
+                drop(wheel);
+
+                if earlier {
+                    self.changed.notify_one();
+                }
+            }
+            Err(waker) => {
+                drop(wheel);
+                waker.wake();
+            }
+        }
+    }
+
+    fn cancel(&self, key: Key) {
+        let waker = self.wheel.lock().unwrap().remove(key);
+
+        // Dropped outside of the lock.
+        drop(waker);
+    }
+
+    fn drive(&self) {
+        let mut wheel = self.wheel.lock().unwrap();
+
+        loop {
+            let fired = wheel.advance(self.elapsed_at(Instant::now()));
+            if !fired.is_empty() {
+                drop(wheel);
+                fired.into_iter().for_each(Waker::wake);
+                wheel = self.wheel.lock().unwrap();
+                continue;
+            }
+
+            wheel = match wheel.next_expiration() {
+                Some(next) => {
+                    let timeout = self
+                        .instant_at(next)
+                        .saturating_duration_since(Instant::now());
+                    self.changed.wait_timeout(wheel, timeout).unwrap().0
+                }
+                None => self.changed.wait(wheel).unwrap(),
+            };
+        }
+    }
+}
+
+thread_local! {
+    /// Timers of the simulations alive on the thread, the newest last.
+    static OVERRIDES: RefCell<Vec<Handle>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Handle to the timer futures register with.
+#[derive(Clone)]
+pub(crate) struct Handle {
+    timer: Arc<Timer>,
+}
+
+impl Handle {
+    /// The thread's newest simulated timer if there is one, the global one
+    /// otherwise.
+    pub(crate) fn current() -> Self {
+        static GLOBAL: OnceLock<Handle> = OnceLock::new();
+
+        if let Some(handle) = OVERRIDES.with(|handles| handles.borrow().last().cloned()) {
+            return handle;
+        }
+
+        GLOBAL
+            .get_or_init(|| {
+                let timer = Arc::new(Timer::new(false));
+
+                let driver = Arc::clone(&timer);
+                thread::Builder::new()
+                    .name("timer".to_owned())
+                    .spawn(move || driver.drive())
+                    .unwrap();
+
+                Handle { timer }
+            })
+            .clone()
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        self.timer.now()
+    }
+
+    /// A timer on a virtual clock, with no thread driving it.
+    pub(crate) fn simulated() -> Self {
+        Handle {
+            timer: Arc::new(Timer::new(true)),
+        }
+    }
+
+    /// Makes this timer what `current` returns on this thread until it leaves.
+    pub(crate) fn enter(&self) {
+        OVERRIDES.with(|handles| handles.borrow_mut().push(self.clone()));
+    }
+
+    /// Hands the thread back to the timer that entered before this one, or to
+    /// the global one. Timers may leave in any order.
+    pub(crate) fn leave(&self) {
+        OVERRIDES.with(|handles| {
+            handles
+                .borrow_mut()
+                .retain(|handle| !Arc::ptr_eq(&handle.timer, &self.timer))
+        });
+    }
+
+    /// Jumps the virtual clock to the next deadline and wakes every timer due
+    /// by then. Returns `false`, leaving the clock alone, if no timer is
+    /// pending.
+    pub(crate) fn advance(&self) -> bool {
+        let Clock::Virtual(now) = &self.timer.clock else {
+            panic!("only a simulated clock can be advanced");
+        };
+
+        let mut wheel = self.timer.wheel.lock().unwrap();
+        let Some(next) = wheel.next_expiration() else {
+            return false;
+        };
+
+        let mut now = now.lock().unwrap();
+        *now = (*now).max(self.timer.instant_at(next));
+        let fired = wheel.advance(self.timer.elapsed_at(*now));
+        drop(now);
+        drop(wheel);
+
+        fired.into_iter().for_each(Waker::wake);
+        true
+    }
+}
+
+/// `duration` after `now`, or a deadline so far out that it never comes if
+/// that is more than `Instant` can hold.
+fn deadline_after(now: Instant, duration: Duration) -> Instant {
+    const FAR_FUTURE: Duration = Duration::from_secs(30 * 365 * 24 * 60 * 60);
+
+    now.checked_add(duration)
+        .unwrap_or_else(|| now + FAR_FUTURE)
+}
+
+/// Sleep
+///
+/// Completes once its deadline has passed.
+pub struct Sleep {
+    deadline: Instant,
+    handle: Handle,
+    key: Option<Key>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        self.handle.now() >= self.deadline
+    }
+
+    /// Moves the deadline, whether or not the sleep has completed.
+    pub fn reset(&mut self, deadline: Instant) {
+        if let Some(key) = self.key.take() {
+            self.handle.timer.cancel(key);
+        }
+        self.deadline = deadline;
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if this.is_elapsed() {
+            if let Some(key) = this.key.take() {
+                this.handle.timer.cancel(key);
+            }
+            return Poll::Ready(());
+        }
+
+        this.handle
+            .timer
+            .register(this.deadline, cx.waker(), &mut this.key);
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.handle.timer.cancel(key);
+        }
+    }
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    let handle = Handle::current();
+
+    Sleep {
+        deadline: deadline_after(handle.now(), duration),
+        handle,
+        key: None,
+    }
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        handle: Handle::current(),
+        key: None,
+    }
+}
+
+/// Interval
+///
+/// Ticks every `period`. Ticks missed because the owner was busy are skipped
+/// rather than delivered in a burst.
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Completes at the next tick, returning when it was due.
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let due = self.sleep.deadline();
+        let now = self.sleep.handle.now();
+        let mut next = due + self.period;
+        while next <= now {
+            next += self.period;
+        }
+        self.sleep.reset(next);
+
+        Poll::Ready(due)
+    }
+}
+
+/// The first tick completes immediately.
+///
+/// # Panics
+///
+/// If `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+
+    let handle = Handle::current();
+
+    Interval {
+        period,
+        sleep: Sleep {
+            deadline: handle.now(),
+            handle,
+            key: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{executor::Executor, sim::Sim};
+
+    #[test]
+    fn sleep_waits_at_least_its_duration() {
+        let executor = Executor::new();
+        let started = Instant::now();
+
+        executor.block_on(sleep(Duration::from_millis(30)));
+
+        assert!(started.elapsed() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn runs_many_timers_without_threads() {
+        let executor = Executor::new();
+        let completed = Rc::new(Cell::new(0));
+
+        for index in 0..10_000u64 {
+            let completed = Rc::clone(&completed);
+            executor.spawn(async move {
+                sleep(Duration::from_millis(1 + index % 50)).await;
+                completed.set(completed.get() + 1);
+            });
+        }
+
+        executor.block_on(async {
+            while completed.get() < 10_000 {
+                sleep(Duration::from_millis(5)).await;
+            }
+        });
+    }
+
+    #[test]
+    fn sleeps_longer_than_u32_ticks_complete() {
+        let sim = Sim::new(1);
+        let days = Duration::from_secs(60 * 24 * 60 * 60);
+
+        sim.block_on(sleep(days));
+
+        assert_eq!(sim.elapsed(), days);
+    }
+
+    #[test]
+    fn sleeping_forever_never_fires() {
+        let sim = Sim::new(1);
+
+        let result = sim.block_on(timeout(Duration::from_secs(1), sleep(Duration::MAX)));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn dropped_sleeps_leave_the_wheel() {
+        let handle = Handle::current();
+        let mut pending = sleep(Duration::from_secs(3600));
+
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        assert!(Pin::new(&mut pending).poll(&mut cx).is_pending());
+
+        let key = pending.key.unwrap();
+        drop(pending);
+
+        assert!(handle.timer.wheel.lock().unwrap().get_mut(key).is_none());
+    }
+
+    #[test]
+    fn interval_ticks_every_period() {
+        let sim = Sim::new(1);
+        let period = Duration::from_millis(10);
+
+        let ticks = sim.block_on(async {
+            let mut interval = interval(period);
+            let mut ticks = Vec::new();
+            for _ in 0..4 {
+                ticks.push(interval.tick().await);
+            }
+            ticks
+        });
+
+        for pair in ticks.windows(2) {
+            assert_eq!(pair[1] - pair[0], period);
+        }
+        assert_eq!(sim.elapsed(), period * 3);
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use super::{deadline_after, sleep_until, Handle, Sleep};
+
+/// Elapsed
+///
+/// The deadline passed before the future completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+/// Timeout
+///
+/// Races a future against a timer. On expiry the future is dropped right
+/// away, releasing whatever it was waiting on.
+pub struct Timeout<F> {
+    future: Option<Pin<Box<F>>>,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+    pub fn deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        // The future goes first, so one that is ready in time is never lost.
+        let future = this
+            .future
+            .as_mut()
+            .expect("Timeout polled after completion");
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            this.future = None;
+            return Poll::Ready(Ok(output));
+        }
+
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => {
+                this.future = None;
+                Poll::Ready(Err(Elapsed(())))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    let deadline = deadline_after(Handle::current().now(), duration);
+
+    timeout_at(deadline, future)
+}
+
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future: Some(Box::pin(future)),
+        sleep: sleep_until(deadline),
+    }
+}
+
+/// FutureExt
+///
+/// Deadlines as methods, for any future.
+pub trait FutureExt: Future + Sized {
+    fn with_deadline(self, deadline: Instant) -> Timeout<Self> {
+        timeout_at(deadline, self)
+    }
+
+    fn with_timeout(self, duration: Duration) -> Timeout<Self> {
+        timeout(duration, self)
+    }
+}
+
+impl<F: Future> FutureExt for F {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{executor::Executor, my_future::MyFuture, time::sleep};
+
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn completes_within_the_deadline() {
+        let executor = Executor::new();
+
+        let result = executor.block_on(timeout(
+            Duration::from_secs(3600),
+            MyFuture::new(Duration::from_millis(5)),
+        ));
+
+        assert_eq!(result.as_deref(), Ok("We are done!!!"));
+    }
+
+    #[test]
+    fn unbounded_timeout_never_elapses() {
+        let executor = Executor::new();
+
+        let result = executor.block_on(timeout(
+            Duration::MAX,
+            MyFuture::new(Duration::from_millis(5)),
+        ));
+
+        assert_eq!(result.as_deref(), Ok("We are done!!!"));
+    }
+
+    #[test]
+    fn elapses_and_drops_the_future() {
+        let executor = Executor::new();
+        let dropped = Rc::new(Cell::new(false));
+
+        let flag = DropFlag(Rc::clone(&dropped));
+        let mut bounded = Box::pin(timeout(Duration::from_millis(5), async move {
+            let _flag = flag;
+            sleep(Duration::from_secs(3600)).await
+        }));
+
+        let result = executor.block_on(bounded.as_mut());
+
+        assert_eq!(result, Err(Elapsed(())));
+        assert_eq!(result.unwrap_err().to_string(), "deadline has elapsed");
+        // Still owned by `bounded`, yet already dropped.
+        assert!(dropped.get());
+    }
+
+    #[test]
+    fn with_deadline_bounds_any_future() {
+        let executor = Executor::new();
+        let deadline = Instant::now() + Duration::from_millis(5);
+
+        let result =
+            executor.block_on(MyFuture::new(Duration::from_secs(3600)).with_deadline(deadline));
+
+        assert!(result.is_err());
+        assert!(Instant::now() >= deadline);
+    }
+}
@@ -0,0 +1,269 @@
+//! Hierarchical timing wheel.
+//!
+//! Six levels of 64 slots each. A level-0 slot covers one tick, a level-1 slot
+//! 64 ticks, and so on, so the wheel spans 64^6 ticks. An entry is stored at
+//! the lowest level whose slot still distinguishes its deadline from the
+//! current time; when the wheel reaches a higher-level slot its entries are
+//! moved down, until they land in level 0 and fire.
+//!
+//! Inserting is O(1) and removing only touches one slot. Advancing jumps to
+//! the next occupied slot, so the empty ticks in between cost nothing.
+
+use std::collections::HashMap;
+
+const LEVELS: usize = 6;
+const BITS: u32 = 6;
+const SLOTS: u64 = 1 << BITS;
+/// Entries further away are parked at this distance and moved again later.
+const MAX_DELAY: u64 = 1 << (BITS * LEVELS as u32 - 1);
+
+pub(crate) type Key = u64;
+
+struct Entry<T> {
+    when: u64,
+    level: usize,
+    slot: usize,
+    value: T,
+}
+
+struct Level {
+    occupied: u64,
+    slots: Vec<Vec<Key>>,
+}
+
+pub(crate) struct Wheel<T> {
+    elapsed: u64,
+    levels: Vec<Level>,
+    entries: HashMap<Key, Entry<T>>,
+    next_key: Key,
+}
+
+impl<T> Wheel<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                })
+                .collect(),
+            entries: HashMap::new(),
+            next_key: 0,
+        }
+    }
+
+    /// Tick the wheel has advanced to.
+    #[cfg(test)]
+    pub(crate) fn elapsed(&self) -> u64 {
+        self.elapsed
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Stores `value` until tick `when`. Hands it back if `when` has already
+    /// been reached.
+    pub(crate) fn insert(&mut self, when: u64, value: T) -> Result<Key, T> {
+        if when <= self.elapsed {
+            return Err(value);
+        }
+
+        let key = self.next_key;
+        self.next_key += 1;
+
+        let (level, slot) = self.position(when);
+        self.link(key, level, slot);
+        self.entries.insert(
+            key,
+            Entry {
+                when,
+                level,
+                slot,
+                value,
+            },
+        );
+
+        Ok(key)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        self.entries.get_mut(&key).map(|entry| &mut entry.value)
+    }
+
+    pub(crate) fn remove(&mut self, key: Key) -> Option<T> {
+        let entry = self.entries.remove(&key)?;
+        self.unlink(key, entry.level, entry.slot);
+
+        Some(entry.value)
+    }
+
+    /// Tick at which the next occupied slot is reached, if any.
+    pub(crate) fn next_expiration(&self) -> Option<u64> {
+        self.next_slot().map(|(_, _, deadline)| deadline)
+    }
+
+    /// Moves the wheel to tick `now`, returning every value whose deadline has
+    /// been reached.
+    pub(crate) fn advance(&mut self, now: u64) -> Vec<T> {
+        let mut fired = Vec::new();
+
+        while let Some((level, slot, deadline)) = self.next_slot() {
+            if deadline > now {
+                break;
+            }
+            self.elapsed = self.elapsed.max(deadline);
+
+            let keys = std::mem::take(&mut self.levels[level].slots[slot]);
+            self.levels[level].occupied &= !(1 << slot);
+
+            for key in keys {
+                let when = self.entries[&key].when;
+
+                if when <= self.elapsed {
+                    fired.push(self.entries.remove(&key).unwrap().value);
+                } else {
+                    let (level, slot) = self.position(when);
+                    self.link(key, level, slot);
+
+                    let entry = self.entries.get_mut(&key).unwrap();
+                    entry.level = level;
+                    entry.slot = slot;
+                }
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now);
+
+        fired
+    }
+
+    fn position(&self, when: u64) -> (usize, usize) {
+        let when = when.min(self.elapsed + MAX_DELAY);
+
+        // The highest group of bits in which `when` differs from now picks
+        // the level.
+        let masked = (self.elapsed ^ when) | (SLOTS - 1);
+        let significant = 63 - masked.leading_zeros();
+        let level = ((significant / BITS) as usize).min(LEVELS - 1);
+        let slot = (when >> (level as u32 * BITS)) & (SLOTS - 1);
+
+        (level, slot as usize)
+    }
+
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        self.levels.iter().enumerate().find_map(|(level, entries)| {
+            if entries.occupied == 0 {
+                return None;
+            }
+
+            let shift = level as u32 * BITS;
+            let now_slot = (self.elapsed >> shift) & (SLOTS - 1);
+            let ahead = entries
+                .occupied
+                .rotate_right(now_slot as u32)
+                .trailing_zeros() as u64;
+            let slot = (now_slot + ahead) % SLOTS;
+
+            let slot_range = 1u64 << shift;
+            let level_range = slot_range << BITS;
+            let mut deadline = (self.elapsed & !(level_range - 1)) + slot * slot_range;
+            if slot < now_slot {
+                // Only the top level wraps around.
+                deadline += level_range;
+            }
+
+            Some((level, slot as usize, deadline))
+        })
+    }
+
+    fn link(&mut self, key: Key, level: usize, slot: usize) {
+        let level = &mut self.levels[level];
+        level.slots[slot].push(key);
+        level.occupied |= 1 << slot;
+    }
+
+    fn unlink(&mut self, key: Key, level: usize, slot: usize) {
+        let level = &mut self.levels[level];
+        level.slots[slot].retain(|&other| other != key);
+
+        if level.slots[slot].is_empty() {
+            level.occupied &= !(1 << slot);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_entries_in_deadline_order() {
+        let mut wheel = Wheel::new();
+        for when in [5_000_000, 3, 64, 70, 4095, 4096, 300_000] {
+            wheel.insert(when, when).unwrap();
+        }
+
+        let mut fired = Vec::new();
+        while let Some(next) = wheel.next_expiration() {
+            for value in wheel.advance(next) {
+                assert_eq!(value, wheel.elapsed());
+                fired.push(value);
+            }
+        }
+
+        assert_eq!(fired, vec![3, 64, 70, 4095, 4096, 300_000, 5_000_000]);
+    }
+
+    #[test]
+    fn advance_fires_everything_due() {
+        let mut wheel = Wheel::new();
+        for when in 1..=200 {
+            wheel.insert(when, when).unwrap();
+        }
+
+        let mut fired = wheel.advance(100);
+        fired.sort();
+
+        assert_eq!(fired, (1..=100).collect::<Vec<_>>());
+        assert_eq!(wheel.len(), 100);
+        assert_eq!(wheel.next_expiration(), Some(101));
+    }
+
+    #[test]
+    fn can_remove_entries() {
+        let mut wheel = Wheel::new();
+        let key = wheel.insert(10, "removed").unwrap();
+        wheel.insert(20, "kept").unwrap();
+
+        assert_eq!(wheel.remove(key), Some("removed"));
+        assert_eq!(wheel.advance(100), vec!["kept"]);
+        assert_eq!(wheel.next_expiration(), None);
+    }
+
+    #[test]
+    fn rejects_deadlines_already_reached() {
+        let mut wheel = Wheel::new();
+        wheel.advance(50);
+
+        assert_eq!(wheel.insert(50, ()), Err(()));
+        assert!(wheel.insert(51, ()).is_ok());
+    }
+
+    #[test]
+    fn keeps_deadlines_beyond_the_wheel_span() {
+        let mut wheel = Wheel::new();
+        let when = (1 << 40) + 7;
+        wheel.insert(when, ()).unwrap();
+
+        let mut ticks = 0;
+        while wheel.advance(wheel.next_expiration().unwrap()).is_empty() {
+            ticks += 1;
+        }
+
+        assert_eq!(wheel.elapsed(), when);
+        assert!(ticks < 100);
+    }
+}
@@ -0,0 +1,72 @@
+//! Compares the single `Mutex<Inner<T>>` of `mpsc::channel` with the sharded
+//! channel as the number of producers grows.
+//!
+//! Run with `cargo bench -p channels --bench sharded`.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use channels::{
+    mpsc::mpsc,
+    sharded,
+    traits::{RecvChannel, SendChannel},
+};
+
+const MESSAGES: usize = 2_000_000;
+
+/// Times `producers` threads pushing `MESSAGES` in total through the channel
+/// built by `channel`.
+fn run<S, R>(channel: impl FnOnce() -> (S, R), producers: usize) -> Duration
+where
+    S: SendChannel<usize> + Clone + Send + 'static,
+    R: RecvChannel<usize>,
+{
+    let (sender, mut receiver) = channel();
+    let started = Instant::now();
+
+    let handles: Vec<_> = (0..producers)
+        .map(|_| {
+            let mut sender = sender.clone();
+            thread::spawn(move || {
+                for value in 0..MESSAGES / producers {
+                    sender.send(value).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut received = 0;
+    while receiver.receive().is_some() {
+        received += 1;
+    }
+    let elapsed = started.elapsed();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(received, MESSAGES / producers * producers);
+
+    elapsed
+}
+
+fn throughput(elapsed: Duration) -> f64 {
+    MESSAGES as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
+
+fn main() {
+    println!("producers | mpsc (M msg/s) | sharded (M msg/s)");
+
+    for producers in [1, 2, 4, 8, 16] {
+        let mpsc = run(mpsc::channel, producers);
+        let sharded = run(|| sharded::channel(producers), producers);
+
+        println!(
+            "{producers:>9} | {:>14.2} | {:>17.2}",
+            throughput(mpsc),
+            throughput(sharded)
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    thread::{self, JoinHandle},
+};
+
+use crate::mpsc::mpsc::{channel, Receiver, SendError, Sender};
+
+/// Actor
+///
+/// A stateful worker that owns its state and processes one message at a time
+/// on its own thread.
+pub trait Actor: Send + 'static {
+    type Message: Send + 'static;
+
+    fn started(&mut self, _ctx: &mut Context) {}
+
+    fn handle(&mut self, msg: Self::Message, ctx: &mut Context);
+
+    fn stopped(&mut self) {}
+}
+
+/// Context
+///
+/// Handed to the actor on every message so it can control its own lifecycle.
+pub struct Context {
+    restarts: usize,
+    stopping: bool,
+}
+
+impl Context {
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+}
+
+/// Addr
+///
+/// Typed handle used to send messages to a running actor. The actor stops once
+/// every address has been dropped and its mailbox is drained.
+pub struct Addr<A: Actor> {
+    sender: Sender<A::Message>,
+}
+
+impl<A: Actor> Addr<A> {
+    /// Fails, handing the message back, once the actor has stopped.
+    pub fn send(&mut self, msg: A::Message) -> Result<(), SendError<A::Message>> {
+        self.sender.send(msg)
+    }
+
+    /// Sends a message carrying a reply slot and blocks until the actor
+    /// answers. Returns `None` if the actor dropped the slot without replying,
+    /// e.g. because it panicked or stopped.
+    pub fn ask<R, F>(&mut self, f: F) -> Option<R>
+    where
+        F: FnOnce(ReplyTo<R>) -> A::Message,
+    {
+        let (sender, mut receiver) = channel();
+        self.sender.send(f(ReplyTo { sender })).ok()?;
+
+        receiver.receive()
+    }
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// ReplyTo
+///
+/// One-shot reply slot embedded in request messages.
+pub struct ReplyTo<R> {
+    sender: Sender<R>,
+}
+
+impl<R> ReplyTo<R> {
+    /// Does nothing if the asker has stopped waiting.
+    pub fn reply(mut self, value: R) {
+        let _ = self.sender.send(value);
+    }
+}
+
+/// Starts `actor` on a dedicated thread. A panic while handling a message stops
+/// the actor.
+pub fn start<A: Actor>(actor: A) -> (Addr<A>, JoinHandle<()>) {
+    let mut actor = Some(actor);
+
+    supervise(move || actor.take().expect("actor is never restarted"), 0)
+}
+
+/// Starts the actor built by `factory` on a dedicated thread. Whenever handling
+/// a message panics, the actor is rebuilt from `factory` up to `max_restarts`
+/// times before it is stopped for good.
+pub fn supervise<A, F>(mut factory: F, max_restarts: usize) -> (Addr<A>, JoinHandle<()>)
+where
+    A: Actor,
+    F: FnMut() -> A + Send + 'static,
+{
+    let (sender, receiver) = channel::<A::Message>();
+
+    let handle = thread::spawn(move || {
+        let actor = factory();
+        run(actor, factory, receiver, max_restarts);
+    });
+
+    (Addr { sender }, handle)
+}
+
+fn run<A, F>(mut actor: A, mut factory: F, mut receiver: Receiver<A::Message>, max_restarts: usize)
+where
+    A: Actor,
+    F: FnMut() -> A,
+{
+    let mut ctx = Context {
+        restarts: 0,
+        stopping: false,
+    };
+
+    actor.started(&mut ctx);
+
+    while !ctx.stopping {
+        let Some(msg) = receiver.receive() else {
+            break;
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| actor.handle(msg, &mut ctx)));
+
+        if result.is_err() {
+            if ctx.restarts == max_restarts {
+                break;
+            }
+
+            ctx.restarts += 1;
+            actor = factory();
+            actor.started(&mut ctx);
+        }
+    }
+
+    actor.stopped();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        count: i32,
+    }
+
+    enum CounterMsg {
+        Add(i32),
+        Get(ReplyTo<i32>),
+        Restarts(ReplyTo<usize>),
+        Panic,
+        Stop,
+    }
+
+    impl Actor for Counter {
+        type Message = CounterMsg;
+
+        fn handle(&mut self, msg: Self::Message, ctx: &mut Context) {
+            match msg {
+                CounterMsg::Add(value) => self.count += value,
+                CounterMsg::Get(reply) => reply.reply(self.count),
+                CounterMsg::Restarts(reply) => reply.reply(ctx.restarts()),
+                CounterMsg::Panic => panic!("counter panicked"),
+                CounterMsg::Stop => ctx.stop(),
+            }
+        }
+    }
+
+    #[test]
+    fn can_ask_for_state() {
+        let (mut addr, _handle) = start(Counter { count: 0 });
+
+        addr.send(CounterMsg::Add(5)).unwrap();
+        addr.send(CounterMsg::Add(10)).unwrap();
+
+        assert_eq!(addr.ask(CounterMsg::Get), Some(15));
+    }
+
+    #[test]
+    fn stops_when_all_addresses_drop() {
+        let (addr, handle) = start(Counter { count: 0 });
+        let other = addr.clone();
+
+        drop(addr);
+        drop(other);
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn restarts_after_panic() {
+        let (mut addr, _handle) = supervise(|| Counter { count: 100 }, 1);
+
+        addr.send(CounterMsg::Add(1)).unwrap();
+        addr.send(CounterMsg::Panic).unwrap();
+
+        assert_eq!(addr.ask(CounterMsg::Get), Some(100));
+        assert_eq!(addr.ask(CounterMsg::Restarts), Some(1));
+    }
+
+    #[test]
+    fn pending_requests_are_answered_with_none_after_stop() {
+        let (mut addr, handle) = start(Counter { count: 0 });
+
+        addr.send(CounterMsg::Stop).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(addr.ask(CounterMsg::Get), None);
+        assert!(matches!(
+            addr.send(CounterMsg::Add(1)),
+            Err(SendError(CounterMsg::Add(1)))
+        ));
+    }
+
+    struct Fragile {
+        stopped: Sender<()>,
+    }
+
+    impl Actor for Fragile {
+        type Message = ();
+
+        fn handle(&mut self, _msg: Self::Message, _ctx: &mut Context) {
+            panic!("fragile panicked");
+        }
+
+        fn stopped(&mut self) {
+            self.stopped.send(()).unwrap();
+        }
+    }
+
+    #[test]
+    fn stopped_runs_after_the_last_restart() {
+        let (stopped, mut receiver) = channel();
+        let (mut addr, handle) = supervise(
+            move || Fragile {
+                stopped: stopped.clone(),
+            },
+            1,
+        );
+
+        addr.send(()).unwrap();
+        addr.send(()).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(receiver.receive(), Some(()));
+    }
+}
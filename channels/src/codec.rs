@@ -0,0 +1,120 @@
+use std::io::{self, Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Codec
+///
+/// Turns messages into bytes and back for channels that leave the process.
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> io::Result<Vec<u8>>;
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<T>;
+}
+
+/// Bincode
+///
+/// Default codec for any serde type.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bincode;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for Bincode {
+    fn encode(&self, value: &T) -> io::Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> io::Result<T> {
+        bincode::deserialize(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Largest payload a frame may carry. Longer frames are refused on both ends,
+/// so a corrupt length prefix can't make the reader allocate gigabytes.
+pub const MAX_FRAME_LEN: usize = 64 << 20;
+
+/// Writes `payload` prefixed by its length as a big-endian `u32`. Writers
+/// sharing a stream must take turns, as the clones of `ipc::Sender` do.
+pub(crate) fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame too large",
+        ));
+    }
+    let len = payload.len() as u32;
+
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Reads one length-prefixed frame. Returns `None` on a clean end of stream,
+/// i.e. one that ends between frames; a frame cut short is `UnexpectedEof`.
+pub(crate) fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    let mut filled = 0;
+
+    while filled < len.len() {
+        match reader.read(&mut len[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_written_frames() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"one").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(b"one".to_vec()));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"payload").unwrap();
+
+        for cut in [2, 6] {
+            let error = read_frame(&mut &stream[..cut]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn refuses_oversized_frames() {
+        let header = u32::MAX.to_be_bytes();
+        let error = read_frame(&mut header.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let payload = vec![0u8; MAX_FRAME_LEN + 1];
+        let error = write_frame(&mut Vec::new(), &payload).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
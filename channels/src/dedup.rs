@@ -0,0 +1,255 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::mpsc::mpsc::SendError;
+
+/// What a `Sender` does with a message whose key is already queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// The new message takes the place of the pending one, keeping its
+    /// position in the queue.
+    Replace,
+    /// The new message is dropped and the pending one is kept.
+    Keep,
+}
+
+/// Sender
+///
+/// Unbounded sender that never queues two messages with the same key.
+pub struct Sender<K, T> {
+    shared: Arc<Shared<K, T>>,
+}
+
+impl<K: Hash + Eq, T> Sender<K, T> {
+    /// Queues `value`, or folds it into the pending message with the same
+    /// key. Fails, handing the value back, once the receiver is gone.
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        let key = (self.shared.key)(&value);
+
+        let mut inner = self.shared.inner.lock().unwrap();
+        if inner.closed {
+            return Err(SendError(value));
+        }
+
+        if let Some(&seq) = inner.index.get(&key) {
+            // Dropped only once the lock has been released.
+            let _discarded = match self.shared.on_duplicate {
+                OnDuplicate::Replace => {
+                    let position = seq - inner.head;
+                    std::mem::replace(&mut inner.queue[position], value)
+                }
+                OnDuplicate::Keep => value,
+            };
+            drop(inner);
+
+            return Ok(());
+        }
+
+        let seq = inner.head + inner.queue.len();
+        inner.index.insert(key, seq);
+        inner.queue.push_back(value);
+        drop(inner);
+
+        self.shared.receivers_available.notify_one();
+        Ok(())
+    }
+}
+
+impl<K, T> Clone for Sender<K, T> {
+    fn clone(&self) -> Self {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.senders += 1;
+        drop(inner);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<K, T> Drop for Sender<K, T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.senders -= 1;
+
+        let senders = inner.senders;
+        drop(inner);
+
+        if senders == 0 {
+            self.shared.receivers_available.notify_one();
+        }
+    }
+}
+
+/// Receiver
+pub struct Receiver<K, T> {
+    shared: Arc<Shared<K, T>>,
+}
+
+impl<K: Hash + Eq, T> Receiver<K, T> {
+    pub fn receive(&mut self) -> Option<T> {
+        let mut inner = self.shared.inner.lock().unwrap();
+
+        loop {
+            match inner.queue.pop_front() {
+                Some(data) => {
+                    inner.head += 1;
+                    inner.index.remove(&(self.shared.key)(&data));
+
+                    return Some(data);
+                }
+                None if inner.senders == 0 => return None,
+                None => inner = self.shared.receivers_available.wait(inner).unwrap(),
+            }
+        }
+    }
+}
+
+impl<K, T> Drop for Receiver<K, T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.closed = true;
+
+        let queue = std::mem::take(&mut inner.queue);
+        inner.index.clear();
+
+        drop(inner);
+        drop(queue);
+    }
+}
+
+impl<K: Hash + Eq, T> Iterator for Receiver<K, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+struct Inner<K, T> {
+    queue: VecDeque<T>,
+    /// Sequence number of every queued key; `seq - head` is its position.
+    index: HashMap<K, usize>,
+    /// Sequence number of the front of `queue`.
+    head: usize,
+    senders: usize,
+    closed: bool,
+}
+
+struct Shared<K, T> {
+    inner: Mutex<Inner<K, T>>,
+    receivers_available: Condvar,
+    key: Box<dyn Fn(&T) -> K + Send + Sync>,
+    on_duplicate: OnDuplicate,
+}
+
+/// Creates an unbounded channel where at most one message per `key(&message)`
+/// is queued at any time.
+pub fn dedup_channel<K, T, F>(key: F, on_duplicate: OnDuplicate) -> (Sender<K, T>, Receiver<K, T>)
+where
+    K: Hash + Eq,
+    F: Fn(&T) -> K + Send + Sync + 'static,
+{
+    let inner = Inner {
+        queue: VecDeque::new(),
+        index: HashMap::new(),
+        head: 0,
+        senders: 1,
+        closed: false,
+    };
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(inner),
+        receivers_available: Condvar::default(),
+        key: Box::new(key),
+        on_duplicate,
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn replaces_pending_message_in_place() {
+        let (mut sender, receiver) =
+            dedup_channel(|&(key, _): &(&str, u32)| key, OnDuplicate::Replace);
+
+        sender.send(("a", 1)).unwrap();
+        sender.send(("b", 1)).unwrap();
+        sender.send(("a", 2)).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec![("a", 2), ("b", 1)]);
+    }
+
+    #[test]
+    fn keeps_pending_message() {
+        let (mut sender, receiver) =
+            dedup_channel(|&(key, _): &(&str, u32)| key, OnDuplicate::Keep);
+
+        sender.send(("a", 1)).unwrap();
+        sender.send(("a", 2)).unwrap();
+        sender.send(("b", 1)).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec![("a", 1), ("b", 1)]);
+    }
+
+    #[test]
+    fn key_can_be_sent_again_once_received() {
+        let (mut sender, mut receiver) = dedup_channel(|value: &u32| *value, OnDuplicate::Keep);
+
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive(), Some(1));
+
+        sender.send(2).unwrap();
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(receiver.receive(), Some(1));
+    }
+
+    #[test]
+    fn collapses_floods_from_many_senders() {
+        let (sender, receiver) = dedup_channel(|value: &u32| value % 10, OnDuplicate::Replace);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for value in 0..1000 {
+                        sender.send(value).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut keys: Vec<_> = receiver.map(|value| value % 10).collect();
+        keys.sort();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn send_fails_once_the_receiver_is_gone() {
+        let (mut sender, receiver) = dedup_channel(|value: &u32| *value, OnDuplicate::Keep);
+        drop(receiver);
+
+        assert_eq!(sender.send(1), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::codec::{read_frame, write_frame, Bincode, Codec};
+
+const SEGMENT_BYTES: u64 = 4 * 1024 * 1024;
+const SEGMENT_EXTENSION: &str = "seg";
+const ACKED_FILE: &str = "acked";
+
+/// Message
+///
+/// A value handed out by a durable `Receiver`. It is delivered again after a
+/// restart until its `id` has been acknowledged.
+#[derive(Debug, PartialEq)]
+pub struct Message<T> {
+    pub id: u64,
+    pub value: T,
+}
+
+/// Sender
+pub struct Sender<T, C = Bincode> {
+    shared: Arc<Shared<T, C>>,
+}
+
+impl<T, C: Codec<T>> Sender<T, C> {
+    /// Appends `value` to the log. It is also kept in memory when the receiver
+    /// has fewer than `mem_capacity` items ready, otherwise it stays on disk
+    /// until the receiver catches up.
+    pub fn send(&mut self, value: T) -> io::Result<()> {
+        let mut inner = self.shared.inner.lock().unwrap();
+        let id = inner.append(&value)?;
+
+        if inner.read_seq == id && inner.memory.len() < inner.mem_capacity {
+            inner.memory.push_back(Message { id, value });
+            inner.read_seq += 1;
+        }
+
+        drop(inner);
+
+        self.shared.receivers_available.notify_one();
+
+        Ok(())
+    }
+}
+
+impl<T, C> Clone for Sender<T, C> {
+    fn clone(&self) -> Self {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.senders += 1;
+        drop(inner);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T, C> Drop for Sender<T, C> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.senders -= 1;
+
+        let senders = inner.senders;
+
+        drop(inner);
+
+        if senders == 0 {
+            self.shared.receivers_available.notify_one();
+        }
+    }
+}
+
+/// Receiver
+pub struct Receiver<T, C = Bincode> {
+    shared: Arc<Shared<T, C>>,
+}
+
+impl<T, C: Codec<T>> Receiver<T, C> {
+    /// Blocks until a message is available. Returns `Ok(None)` once every
+    /// sender is gone and the log has been fully read.
+    pub fn receive(&mut self) -> io::Result<Option<Message<T>>> {
+        let mut inner = self.shared.inner.lock().unwrap();
+
+        loop {
+            if let Some(message) = inner.memory.pop_front() {
+                return Ok(Some(message));
+            }
+
+            if inner.read_seq < inner.write_seq {
+                inner.load()?;
+            } else if inner.senders == 0 {
+                return Ok(None);
+            } else {
+                inner = self.shared.receivers_available.wait(inner).unwrap();
+            }
+        }
+    }
+
+    /// Marks message `id` as processed. Segments whose messages have all been
+    /// acknowledged are deleted. Acknowledgements may arrive in any order, but
+    /// only the contiguous prefix survives a restart. Ids that have not been
+    /// received yet or are already acknowledged are ignored.
+    pub fn ack(&mut self, id: u64) -> io::Result<()> {
+        let mut inner = self.shared.inner.lock().unwrap();
+
+        // Everything before what is still waiting in memory has been received.
+        let received = inner.read_seq - inner.memory.len() as u64;
+        if id <= inner.acked || id >= received {
+            return Ok(());
+        }
+        inner.pending_acks.insert(id);
+
+        let mut acked = inner.acked;
+        while inner.pending_acks.remove(&(acked + 1)) {
+            acked += 1;
+        }
+
+        if acked != inner.acked {
+            inner.acked = acked;
+            inner.persist_acked()?;
+            inner.delete_acked_segments()?;
+        }
+
+        Ok(())
+    }
+}
+
+struct SegmentReader {
+    base: u64,
+    next_seq: u64,
+    file: BufReader<File>,
+}
+
+struct Inner<T, C> {
+    dir: PathBuf,
+    codec: C,
+    memory: VecDeque<Message<T>>,
+    mem_capacity: usize,
+    senders: usize,
+    segments: VecDeque<u64>,
+    segment_bytes: u64,
+    writer: File,
+    written_bytes: u64,
+    write_seq: u64,
+    read_seq: u64,
+    reader: Option<SegmentReader>,
+    acked: u64,
+    pending_acks: BTreeSet<u64>,
+}
+
+impl<T, C: Codec<T>> Inner<T, C> {
+    fn append(&mut self, value: &T) -> io::Result<u64> {
+        let payload = self.codec.encode(value)?;
+
+        if self.written_bytes >= self.segment_bytes {
+            self.writer = create_segment(&self.dir, self.write_seq)?;
+            self.written_bytes = 0;
+            self.segments.push_back(self.write_seq);
+        }
+
+        write_frame(&mut self.writer, &payload)?;
+        self.written_bytes += 4 + payload.len() as u64;
+
+        let id = self.write_seq;
+        self.write_seq += 1;
+
+        Ok(id)
+    }
+
+    /// Reads spilled messages back from disk until memory is full again.
+    fn load(&mut self) -> io::Result<()> {
+        while self.memory.len() < self.mem_capacity.max(1) && self.read_seq < self.write_seq {
+            let id = self.read_seq;
+            let payload = self.read_record(id)?;
+            let value = self.codec.decode(&payload)?;
+
+            self.memory.push_back(Message { id, value });
+            self.read_seq += 1;
+        }
+
+        Ok(())
+    }
+
+    fn read_record(&mut self, seq: u64) -> io::Result<Vec<u8>> {
+        let base = *self
+            .segments
+            .iter()
+            .rev()
+            .find(|base| **base <= seq)
+            .ok_or(io::ErrorKind::NotFound)?;
+
+        let reusable =
+            matches!(&self.reader, Some(reader) if reader.base == base && reader.next_seq <= seq);
+        if !reusable {
+            let file = File::open(segment_path(&self.dir, base))?;
+            self.reader = Some(SegmentReader {
+                base,
+                next_seq: base,
+                file: BufReader::new(file),
+            });
+        }
+
+        // Messages that went straight to memory were never read back, so the
+        // reader may have to skip over them first.
+        let reader = self.reader.as_mut().unwrap();
+        loop {
+            let payload = read_frame(&mut reader.file)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+            reader.next_seq += 1;
+
+            if reader.next_seq > seq {
+                return Ok(payload);
+            }
+        }
+    }
+}
+
+impl<T, C> Inner<T, C> {
+    fn persist_acked(&self) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{ACKED_FILE}.tmp"));
+        fs::write(&tmp, self.acked.to_be_bytes())?;
+        fs::rename(tmp, self.dir.join(ACKED_FILE))
+    }
+
+    fn delete_acked_segments(&mut self) -> io::Result<()> {
+        while self.segments.len() > 1 && self.segments[1] <= self.acked + 1 {
+            let base = self.segments.pop_front().unwrap();
+            fs::remove_file(segment_path(&self.dir, base))?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Shared<T, C> {
+    inner: Mutex<Inner<T, C>>,
+    receivers_available: Condvar,
+}
+
+fn segment_path(dir: &Path, base: u64) -> PathBuf {
+    dir.join(format!("{base:020}.{SEGMENT_EXTENSION}"))
+}
+
+fn create_segment(dir: &Path, base: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, base))
+}
+
+/// Counts the complete records of the last segment, truncating a record left
+/// half-written by a crash.
+fn recover_segment(path: &Path) -> io::Result<(u64, u64)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut records = 0;
+    let mut valid_bytes = 0;
+
+    while let Ok(Some(payload)) = read_frame(&mut file) {
+        records += 1;
+        valid_bytes += 4 + payload.len() as u64;
+    }
+
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(valid_bytes)?;
+
+    Ok((records, valid_bytes))
+}
+
+/// Opens (or creates) a durable channel backed by the segment files in `dir`.
+/// Messages that were not acknowledged before the previous process exited are
+/// delivered again.
+pub fn durable_channel<T, P: AsRef<Path>>(
+    dir: P,
+    mem_capacity: usize,
+) -> io::Result<(Sender<T>, Receiver<T>)>
+where
+    Bincode: Codec<T>,
+{
+    durable_channel_with_codec(dir, mem_capacity, Bincode)
+}
+
+pub fn durable_channel_with_codec<T, C, P>(
+    dir: P,
+    mem_capacity: usize,
+    codec: C,
+) -> io::Result<(Sender<T, C>, Receiver<T, C>)>
+where
+    C: Codec<T>,
+    P: AsRef<Path>,
+{
+    open(dir.as_ref(), mem_capacity, codec, SEGMENT_BYTES)
+}
+
+fn open<T, C: Codec<T>>(
+    dir: &Path,
+    mem_capacity: usize,
+    codec: C,
+    segment_bytes: u64,
+) -> io::Result<(Sender<T, C>, Receiver<T, C>)> {
+    fs::create_dir_all(dir)?;
+
+    let acked = match fs::read(dir.join(ACKED_FILE)) {
+        Ok(bytes) => u64::from_be_bytes(
+            bytes
+                .try_into()
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?,
+        ),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(err),
+    };
+
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(base) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        {
+            segments.push(base);
+        }
+    }
+    segments.sort_unstable();
+
+    let (segments, write_seq, written_bytes) = match segments.last() {
+        Some(&last) => {
+            let (records, bytes) = recover_segment(&segment_path(dir, last))?;
+            (VecDeque::from(segments), last + records, bytes)
+        }
+        None => (VecDeque::from([acked + 1]), acked + 1, 0),
+    };
+    let writer = create_segment(dir, *segments.back().unwrap())?;
+
+    let inner = Inner {
+        dir: dir.to_path_buf(),
+        codec,
+        memory: VecDeque::new(),
+        mem_capacity,
+        senders: 1,
+        segments,
+        segment_bytes,
+        writer,
+        written_bytes,
+        write_seq,
+        read_seq: acked + 1,
+        reader: None,
+        acked,
+        pending_acks: BTreeSet::new(),
+    };
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(inner),
+        receivers_available: Condvar::default(),
+    });
+
+    Ok((
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process, sync::atomic::AtomicUsize, sync::atomic::Ordering};
+
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir().join(format!(
+            "channels-durable-{}-{}",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn segment_count(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .path()
+                    .extension()
+                    .unwrap_or_default()
+                    == SEGMENT_EXTENSION
+            })
+            .count()
+    }
+
+    #[test]
+    fn spills_overflow_to_disk() {
+        let dir = temp_dir();
+        let (mut sender, mut receiver) = open::<u32, _>(&dir, 2, Bincode, 64).unwrap();
+
+        for value in 0..50 {
+            sender.send(value).unwrap();
+        }
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(message) = receiver.receive().unwrap() {
+            received.push(message.value);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(received, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn redelivers_unacknowledged_messages_after_restart() {
+        let dir = temp_dir();
+
+        let (mut sender, mut receiver) = durable_channel::<String, _>(&dir, 4).unwrap();
+        for value in ["a", "b", "c"] {
+            sender.send(value.to_owned()).unwrap();
+        }
+
+        let first = receiver.receive().unwrap().unwrap();
+        receiver.ack(first.id).unwrap();
+        let _unacked = receiver.receive().unwrap().unwrap();
+        drop((sender, receiver));
+
+        let (sender, mut receiver) = durable_channel::<String, _>(&dir, 4).unwrap();
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(message) = receiver.receive().unwrap() {
+            received.push(message.value);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(received, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn ignores_acks_for_messages_not_in_flight() {
+        let dir = temp_dir();
+        let (mut sender, mut receiver) = durable_channel::<u32, _>(&dir, 4).unwrap();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        let first = receiver.receive().unwrap().unwrap();
+        receiver.ack(first.id).unwrap();
+        for id in [0, first.id, first.id + 1, first.id + 1000] {
+            receiver.ack(id).unwrap();
+        }
+
+        let pending = receiver.shared.inner.lock().unwrap().pending_acks.len();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(pending, 0);
+    }
+
+    #[test]
+    fn deletes_fully_acknowledged_segments() {
+        let dir = temp_dir();
+        let (mut sender, mut receiver) = open::<u64, _>(&dir, 1, Bincode, 24).unwrap();
+
+        for value in 0..20 {
+            sender.send(value).unwrap();
+        }
+        assert!(segment_count(&dir) > 1);
+
+        for _ in 0..20 {
+            let message = receiver.receive().unwrap().unwrap();
+            receiver.ack(message.id).unwrap();
+        }
+
+        let remaining = segment_count(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(remaining, 1);
+    }
+}
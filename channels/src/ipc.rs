@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    io::{self, BufReader},
+    marker::PhantomData,
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::codec::{read_frame, write_frame, Bincode, Codec};
+
+/// Sender
+///
+/// Writing half of a channel whose receiver may live in another process.
+/// Every message travels as a length-prefixed frame produced by the codec.
+pub struct Sender<T, C = Bincode> {
+    stream: Arc<Mutex<UnixStream>>,
+    codec: C,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T, C: Codec<T>> Sender<T, C> {
+    pub fn from_stream(stream: UnixStream, codec: C) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(stream)),
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Fails once the receiving end has been closed, e.g. because the peer
+    /// process exited.
+    pub fn send(&mut self, value: T) -> io::Result<()> {
+        let payload = self.codec.encode(&value)?;
+        let mut stream = self.stream.lock().unwrap();
+
+        write_frame(&mut *stream, &payload)
+    }
+}
+
+impl<T, C: Clone> Clone for Sender<T, C> {
+    fn clone(&self) -> Self {
+        Self {
+            stream: Arc::clone(&self.stream),
+            codec: self.codec.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Receiver
+pub struct Receiver<T, C = Bincode> {
+    stream: BufReader<UnixStream>,
+    codec: C,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, C: Codec<T>> Receiver<T, C> {
+    pub fn from_stream(stream: UnixStream, codec: C) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Blocks until the next message arrives. Returns `None` once every sender
+    /// is gone or the stream can no longer be decoded.
+    pub fn receive(&mut self) -> Option<T> {
+        let payload = read_frame(&mut self.stream).ok()??;
+
+        self.codec.decode(&payload).ok()
+    }
+}
+
+impl<T, C: Codec<T>> Iterator for Receiver<T, C> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+/// Creates a connected socket pair. Either half can be handed to a forked
+/// child process.
+pub fn channel<T>() -> io::Result<(Sender<T>, Receiver<T>)>
+where
+    Bincode: Codec<T>,
+{
+    let (sender, receiver) = UnixStream::pair()?;
+
+    Ok((
+        Sender::from_stream(sender, Bincode),
+        Receiver::from_stream(receiver, Bincode),
+    ))
+}
+
+/// Connects to a receiver listening on the socket at `path`.
+pub fn connect<T, P: AsRef<Path>>(path: P) -> io::Result<Sender<T>>
+where
+    Bincode: Codec<T>,
+{
+    let stream = UnixStream::connect(path)?;
+
+    Ok(Sender::from_stream(stream, Bincode))
+}
+
+/// Binds a socket at `path` and waits for a single peer to connect. The socket
+/// file is removed again afterwards, so `path` can be listened on once more.
+pub fn listen<T, P: AsRef<Path>>(path: P) -> io::Result<Receiver<T>>
+where
+    Bincode: Codec<T>,
+{
+    let listener = UnixListener::bind(&path)?;
+    let accepted = listener.accept();
+    let _ = fs::remove_file(path);
+    let (stream, _) = accepted?;
+
+    Ok(Receiver::from_stream(stream, Bincode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, process, thread};
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Job {
+        id: u32,
+        name: String,
+    }
+
+    #[test]
+    fn can_send_and_receive() {
+        let (mut sender, mut receiver) = channel::<Job>().unwrap();
+
+        let handle = thread::spawn(move || {
+            for id in 0..3 {
+                sender
+                    .send(Job {
+                        id,
+                        name: format!("job-{id}"),
+                    })
+                    .unwrap();
+            }
+        });
+
+        handle.join().unwrap();
+
+        assert_eq!(receiver.receive().unwrap().name, "job-0");
+        assert_eq!(receiver.map(|job| job.id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn send_fails_once_receiver_is_gone() {
+        let (mut sender, receiver) = channel::<u64>().unwrap();
+
+        drop(receiver);
+
+        assert!(sender.send(1).is_err());
+    }
+
+    #[test]
+    fn can_connect_to_named_socket() {
+        let path = env::temp_dir().join(format!("channels-ipc-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listen_path = path.clone();
+        let handle = thread::spawn(move || listen::<String, _>(listen_path).unwrap().collect());
+
+        let mut sender = loop {
+            if let Ok(sender) = connect::<String, _>(&path) {
+                break sender;
+            }
+            thread::yield_now();
+        };
+        sender.send("hello".to_owned()).unwrap();
+        drop(sender);
+
+        let received: Vec<String> = handle.join().unwrap();
+
+        assert_eq!(received, vec!["hello".to_owned()]);
+    }
+
+    #[test]
+    fn can_listen_on_a_path_again() {
+        let path = env::temp_dir().join(format!("channels-ipc-again-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        for round in 0..2 {
+            let listen_path = path.clone();
+            let handle = thread::spawn(move || listen::<u32, _>(listen_path).unwrap().collect());
+
+            let mut sender = loop {
+                if let Ok(sender) = connect::<u32, _>(&path) {
+                    break sender;
+                }
+                thread::yield_now();
+            };
+            sender.send(round).unwrap();
+            drop(sender);
+
+            let received: Vec<u32> = handle.join().unwrap();
+            assert_eq!(received, vec![round]);
+        }
+
+        assert!(!path.exists());
+    }
+}
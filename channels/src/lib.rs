@@ -0,0 +1,16 @@
+pub mod actor;
+pub mod codec;
+pub mod dedup;
+pub mod durable;
+#[cfg(unix)]
+pub mod ipc;
+pub mod lockfree;
+pub mod mpsc;
+pub mod net;
+pub mod pipeline;
+pub mod pubsub;
+pub mod rate_limit;
+pub mod rpc;
+pub mod sharded;
+pub mod traits;
+pub mod ttl;
@@ -0,0 +1,289 @@
+//! Bounded channel on top of a lock-free ring buffer (Dmitry Vyukov's bounded
+//! MPMC queue). Neither side ever takes a lock: a full or empty queue is
+//! handled by spinning and then yielding to the scheduler, which trades CPU
+//! for latency compared to the `Condvar` parking of `mpsc::sync_channel`.
+
+use std::{
+    cell::UnsafeCell,
+    hint,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crate::mpsc::mpsc::{SendError, TrySendError};
+
+const SPINS_BEFORE_YIELD: u32 = 64;
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Shared<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+}
+
+// Slots are handed over between threads through the `seq` protocol, so a value
+// is only ever accessed by the thread that claimed its slot.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+
+            match (seq as isize).wrapping_sub(pos as isize) {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                diff if diff < 0 => return Err(value),
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+
+            match (seq as isize).wrapping_sub(pos as isize + 1) {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.seq.store(pos + self.mask + 1, Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                },
+                diff if diff < 0 => return None,
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+fn backoff(spins: &mut u32) {
+    if *spins < SPINS_BEFORE_YIELD {
+        *spins += 1;
+        hint::spin_loop();
+    } else {
+        thread::yield_now();
+    }
+}
+
+/// SyncSender
+pub struct SyncSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> SyncSender<T> {
+    /// Waits for a free slot. Fails, handing the value back, once the receiver
+    /// is gone.
+    pub fn send(&mut self, mut value: T) -> Result<(), SendError<T>> {
+        let mut spins = 0;
+
+        loop {
+            match self.try_send(value) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(rejected)) => return Err(SendError(rejected)),
+                Err(TrySendError::Full(rejected)) => value = rejected,
+            }
+
+            backoff(&mut spins);
+        }
+    }
+
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return Err(TrySendError::Disconnected(value));
+        }
+
+        self.shared.push(value).map_err(TrySendError::Full)
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.shared.senders.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Receiver
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn receive(&mut self) -> Option<T> {
+        let mut spins = 0;
+
+        loop {
+            if let Some(value) = self.shared.pop() {
+                return Some(value);
+            }
+
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                // A sender may have pushed right before dropping.
+                return self.shared.pop();
+            }
+
+            backoff(&mut spins);
+        }
+    }
+
+    /// Returns `None` when the queue is empty right now, whether or not
+    /// senders are still connected.
+    pub fn try_receive(&mut self) -> Option<T> {
+        self.shared.pop()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+/// The capacity `sync_channel(capacity)` actually allocates: the next power of
+/// two, and at least 2, so slots can be addressed with a mask.
+pub fn rounded_capacity(capacity: usize) -> usize {
+    capacity.max(2).next_power_of_two()
+}
+
+/// Creates a bounded channel of `rounded_capacity(capacity)` slots.
+pub fn sync_channel<T>(capacity: usize) -> (SyncSender<T>, Receiver<T>) {
+    let capacity = rounded_capacity(capacity);
+    let slots = (0..capacity)
+        .map(|seq| Slot {
+            seq: AtomicUsize::new(seq),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+
+    let shared = Arc::new(Shared {
+        slots,
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        senders: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+    });
+
+    (
+        SyncSender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_full_queue() {
+        let (mut sender, mut receiver) = sync_channel(2);
+
+        assert_eq!(sender.try_send(1), Ok(()));
+        assert_eq!(sender.try_send(2), Ok(()));
+        assert_eq!(sender.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(receiver.try_receive(), Some(1));
+        assert_eq!(sender.try_send(3), Ok(()));
+    }
+
+    #[test]
+    fn delivers_everything_from_many_producers() {
+        let (sender, receiver) = sync_channel(8);
+
+        let handles: Vec<_> = (0..4)
+            .map(|producer| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for seq in 0..10_000 {
+                        sender.send((producer, seq)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut next = [0; 4];
+        for (producer, seq) in receiver {
+            assert_eq!(seq, next[producer]);
+            next[producer] += 1;
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(next, [10_000; 4]);
+    }
+
+    #[test]
+    fn drops_values_left_in_the_queue() {
+        let value = Arc::new(());
+        let (mut sender, receiver) = sync_channel(4);
+
+        sender.send(Arc::clone(&value)).unwrap();
+        sender.send(Arc::clone(&value)).unwrap();
+        drop((sender, receiver));
+
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -1,69 +1,129 @@
-mod mpsc;
+use std::{env, process};
 
-use std::{thread, time::Duration};
+use scenario::{Backend, Report, Scenario};
 
-use mpsc::mpsc::sync_channel;
+mod scenario;
+
+const USAGE: &str = "\
+Usage: channels run [OPTIONS]
+
+Options:
+  --producers <N>   producer threads (default 3)
+  --consumers <N>   consumer threads sharing the receiver (default 1)
+  --capacity <N>    channel capacity, rounded up to a power of two by
+                    lockfree (default 3)
+  --messages <N>    messages in total, e.g. 1e6 (default 1e6)
+  --backend <NAME>  mutex | lockfree | std (default mutex)";
+
+fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    // Accept scientific notation such as `1e6`.
+    value
+        .parse::<usize>()
+        .ok()
+        .or_else(|| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|count| count.is_finite() && *count >= 0.0 && count.fract() == 0.0)
+                .map(|count| count as usize)
+        })
+        .ok_or_else(|| format!("invalid value '{value}' for {flag}"))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Scenario, String> {
+    match args.next().as_deref() {
+        Some("run") => {}
+        Some(command) => return Err(format!("unknown command '{command}'")),
+        None => return Err("missing command".to_owned()),
+    }
+
+    let mut scenario = Scenario::default();
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {flag}"))?;
+
+        match flag.as_str() {
+            "--producers" => scenario.producers = parse_count(&flag, &value)?,
+            "--consumers" => scenario.consumers = parse_count(&flag, &value)?,
+            "--capacity" => scenario.capacity = parse_count(&flag, &value)?,
+            "--messages" => scenario.messages = parse_count(&flag, &value)?,
+            "--backend" => {
+                scenario.backend =
+                    Backend::parse(&value).ok_or_else(|| format!("unknown backend '{value}'"))?
+            }
+            _ => return Err(format!("unknown option '{flag}'")),
+        }
+    }
+
+    if scenario.producers == 0 || scenario.consumers == 0 || scenario.capacity == 0 {
+        return Err("--producers, --consumers and --capacity must be at least 1".to_owned());
+    }
+
+    Ok(scenario)
+}
+
+fn print_report(scenario: &Scenario, report: &Report) {
+    let per_message = |blocks: usize| blocks as f64 / report.received.max(1) as f64 * 100.0;
+
+    let capacity = scenario.backend.capacity(scenario.capacity);
+    print!(
+        "backend {}, {} producers, {} consumers, capacity {capacity}",
+        scenario.backend, scenario.producers, scenario.consumers
+    );
+    if capacity != scenario.capacity {
+        print!(" (asked for {})", scenario.capacity);
+    }
+    println!();
+    println!(
+        "received   {} messages in {:.3?}",
+        report.received, report.elapsed
+    );
+    println!("throughput {:.0} msg/s", report.throughput());
+    println!(
+        "latency    p50 {:.1?}  p90 {:.1?}  p99 {:.1?}  p99.9 {:.1?}  max {:.1?}",
+        report.percentile(50.0),
+        report.percentile(90.0),
+        report.percentile(99.0),
+        report.percentile(99.9),
+        report.percentile(100.0),
+    );
+    println!(
+        "blocked    {} sends ({:.2}%), {} receives ({:.2}%)",
+        report.send_blocks,
+        per_message(report.send_blocks),
+        report.receive_blocks,
+        per_message(report.receive_blocks),
+    );
+}
 
 fn main() {
-    let (sender, receiver) = sync_channel::<i32>(3);
-
-    let mut sender_1 = sender.clone();
-    let sender_handle_1 = thread::spawn(move || {
-        println!("Send (11)");
-        sender_1.send(11);
-        println!("Send (12)");
-        sender_1.send(12);
-        println!("Send (13)");
-        sender_1.send(13);
-    });
-
-    let mut sender_2 = sender.clone();
-    let sender_handle_2 = thread::spawn(move || {
-        // thread::sleep(Duration::from_secs(2));
-
-        println!("Send (21)");
-        sender_2.send(21);
-        println!("Send (22)");
-        sender_2.send(22);
-        println!("Send (23)");
-        sender_2.send(23);
-    });
-
-    let mut sender_3 = sender;
-
-    let sender_handle_3 = thread::spawn(move || {
-        // thread::sleep(Duration::from_secs(4));
-
-        println!("Send (31)");
-        sender_3.send(31);
-        println!("Send (32)");
-        sender_3.send(32);
-        println!("Send (33)");
-        sender_3.send(33);
-    });
-
-    // let receiver_handle = thread::spawn(move || loop {
-    //     if let Some(data) = receiver.receive() {
-    //         println!("Received {data}");
-    //     } else {
-    //         println!("No more senders...");
-    //         break;
-    //     }
-    // });
-
-    let receiver_handle = thread::spawn(move || {
-        thread::sleep(Duration::from_secs(5));
-        for data in receiver {
-            println!("Received {data}");
-            thread::sleep(Duration::from_secs(1));
+    let scenario = match parse_args(env::args().skip(1)) {
+        Ok(scenario) => scenario,
+        Err(error) => {
+            eprintln!("error: {error}\n\n{USAGE}");
+            process::exit(2);
         }
-        println!("No more senders...");
-    });
+    };
 
-    let _ = receiver_handle.join().unwrap();
-    let _ = sender_handle_1.join().unwrap();
-    let _ = sender_handle_2.join().unwrap();
-    let _ = sender_handle_3.join().unwrap();
+    let report = scenario::run(scenario);
+    print_report(&scenario, &report);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Hello, world!");
+    fn parse(args: &str) -> Result<Scenario, String> {
+        parse_args(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn rejects_counts_that_would_hang() {
+        for flag in ["--producers", "--consumers", "--capacity"] {
+            assert!(parse(&format!("run {flag} 0")).is_err(), "{flag}");
+        }
+        assert_eq!(parse("run --capacity 1e3").unwrap().capacity, 1000);
+    }
 }
@@ -0,0 +1,396 @@
+//! Opt-in wait-for graph for the bounded channels, enabled by the
+//! `deadlock-detection` feature.
+//!
+//! Every thread about to block in `SyncSender::send` or `Receiver::receive`
+//! registers what it waits for. A thread blocked on a full channel can only be
+//! released by the thread holding its receiver, and a thread blocked on an
+//! empty channel by the threads holding its senders. If every thread that
+//! could release a blocked thread is itself blocked, none of them will ever
+//! wake up, so the thread that closes the cycle panics with a report instead
+//! of hanging.
+//!
+//! Handles can move between threads unnoticed, so each live handle is
+//! attributed to the thread that last used it. That makes detection
+//! best-effort in both directions:
+//!
+//! - A handle that has not been used yet, or whose thread has exited, could be
+//!   anywhere, so nothing waiting on its channel is ever reported.
+//! - A handle used on one thread and then moved to another still counts as the
+//!   first thread's until it is used again. If the first thread blocks, a
+//!   thread waiting on that channel can be reported even though the new owner
+//!   could still release it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    thread::{self, ThreadId},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    Send,
+    Receive,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Wait {
+    channel: usize,
+    op: Op,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Owner {
+    /// `Send` for senders, `Receive` for the receiver.
+    op: Op,
+    /// Thread that last used the handle, if it is still running.
+    thread: Option<ThreadId>,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Live handles by channel, then by handle id.
+    handles: HashMap<usize, HashMap<usize, Owner>>,
+    blocked: HashMap<ThreadId, Wait>,
+    names: HashMap<ThreadId, String>,
+}
+
+impl Registry {
+    /// Threads holding a handle that could release `wait`, or `None` if some
+    /// of those handles can't be attributed to a thread.
+    fn unblockers(&self, wait: Wait) -> Option<Vec<ThreadId>> {
+        let releasing = match wait.op {
+            Op::Send => Op::Receive,
+            Op::Receive => Op::Send,
+        };
+
+        self.handles
+            .get(&wait.channel)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .filter(|owner| owner.op == releasing)
+            .map(|owner| owner.thread)
+            .collect()
+    }
+
+    /// Largest set of blocked threads that can only be released by threads in
+    /// the same set.
+    fn deadlocked(&self) -> HashSet<ThreadId> {
+        let mut stuck: HashSet<ThreadId> = self.blocked.keys().copied().collect();
+
+        loop {
+            let still_stuck: HashSet<ThreadId> = stuck
+                .iter()
+                .copied()
+                .filter(|thread| match self.unblockers(self.blocked[thread]) {
+                    Some(unblockers) => {
+                        !unblockers.is_empty()
+                            && unblockers.iter().all(|other| stuck.contains(other))
+                    }
+                    None => false,
+                })
+                .collect();
+
+            if still_stuck.len() == stuck.len() {
+                return stuck;
+            }
+            stuck = still_stuck;
+        }
+    }
+
+    fn name(&self, thread: &ThreadId) -> &str {
+        self.names.get(thread).map_or("<unnamed>", String::as_str)
+    }
+
+    fn exited(&mut self, thread: ThreadId) {
+        self.names.remove(&thread);
+        self.blocked.remove(&thread);
+
+        for owner in self.handles.values_mut().flat_map(HashMap::values_mut) {
+            if owner.thread == Some(thread) {
+                owner.thread = None;
+            }
+        }
+    }
+}
+
+/// Report
+///
+/// Describes every thread in a wait-for cycle.
+#[derive(Debug)]
+pub struct Report {
+    description: String,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+    REGISTRY.get_or_init(Mutex::default)
+}
+
+/// Forgets its thread when the thread exits.
+struct ExitGuard(ThreadId);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = registry().lock() {
+            registry.exited(self.0);
+        }
+    }
+}
+
+thread_local! {
+    static EXIT_GUARD: ExitGuard = ExitGuard(thread::current().id());
+}
+
+fn current() -> ThreadId {
+    let thread = thread::current();
+    let id = thread.id();
+
+    // Fails only while the thread is already exiting.
+    if EXIT_GUARD.try_with(|_| {}).is_err() {
+        return id;
+    }
+
+    registry()
+        .lock()
+        .unwrap()
+        .names
+        .entry(id)
+        .or_insert_with(|| thread.name().unwrap_or("<unnamed>").to_owned());
+
+    id
+}
+
+/// Handle
+///
+/// Registers one `Sender`, `SyncSender` or `Receiver` for as long as it lives.
+pub(crate) struct Handle {
+    id: usize,
+    channel: usize,
+}
+
+impl Handle {
+    pub(crate) fn new(channel: usize, op: Op) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        registry()
+            .lock()
+            .unwrap()
+            .handles
+            .entry(channel)
+            .or_default()
+            .insert(id, Owner { op, thread: None });
+
+        Self { id, channel }
+    }
+
+    /// Attributes the handle to the current thread.
+    pub(crate) fn used(&self) {
+        let thread = current();
+
+        let mut registry = registry().lock().unwrap();
+        if let Some(owner) = registry
+            .handles
+            .get_mut(&self.channel)
+            .and_then(|handles| handles.get_mut(&self.id))
+        {
+            owner.thread = Some(thread);
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let Ok(mut registry) = registry().lock() else {
+            return;
+        };
+
+        if let Some(handles) = registry.handles.get_mut(&self.channel) {
+            handles.remove(&self.id);
+            if handles.is_empty() {
+                registry.handles.remove(&self.channel);
+            }
+        }
+    }
+}
+
+/// Registers the current thread as blocked on `channel`. Fails with a report
+/// when doing so closes a cycle the thread would never wake up from.
+pub(crate) fn block(channel: usize, op: Op) -> Result<(), Report> {
+    let thread = current();
+    let mut registry = registry().lock().unwrap();
+    registry.blocked.insert(thread, Wait { channel, op });
+
+    let deadlocked = registry.deadlocked();
+    if !deadlocked.contains(&thread) {
+        return Ok(());
+    }
+
+    let mut description = String::from("deadlock detected between bounded channels:");
+    for stuck in &deadlocked {
+        let wait = registry.blocked[stuck];
+        let others: Vec<&str> = registry
+            .unblockers(wait)
+            .unwrap_or_default()
+            .iter()
+            .map(|other| registry.name(other))
+            .collect();
+        let action = match wait.op {
+            Op::Send => "sending into full",
+            Op::Receive => "receiving from empty",
+        };
+
+        let _ = write!(
+            description,
+            "\n  thread '{}' is blocked {action} channel {:#x}, waiting for '{}'",
+            registry.name(stuck),
+            wait.channel,
+            others.join("', '"),
+        );
+    }
+
+    registry.blocked.remove(&thread);
+
+    Err(Report { description })
+}
+
+/// Called by whoever notifies the waiters of `channel`. They are no longer
+/// blocked even though they may not have been scheduled yet.
+pub(crate) fn woke(channel: usize, op: Op) {
+    registry()
+        .lock()
+        .unwrap()
+        .blocked
+        .retain(|_, wait| wait.channel != channel || wait.op != op);
+}
+
+pub(crate) fn unblock() {
+    let thread = thread::current().id();
+
+    registry().lock().unwrap().blocked.remove(&thread);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::mpsc::mpsc::sync_channel;
+
+    #[test]
+    fn detects_sending_into_channel_only_self_drains() {
+        let handle = thread::Builder::new()
+            .name("lonely-worker".to_owned())
+            .spawn(|| {
+                let (mut sender, mut receiver) = sync_channel(1);
+
+                sender.send(1).unwrap();
+                let _ = receiver.receive();
+                sender.send(2).unwrap();
+                sender.send(3).unwrap();
+            })
+            .unwrap();
+
+        let panic = handle.join().unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+
+        assert!(message.contains("'lonely-worker' is blocked sending into full"));
+    }
+
+    #[test]
+    fn detects_cycle_between_two_threads() {
+        let (mut to_b, mut from_a) = sync_channel::<u32>(1);
+        let (mut to_a, mut from_b) = sync_channel::<u32>(1);
+
+        // Each side drains its own channel once, then floods the other one.
+        to_a.send(0).unwrap();
+        to_b.send(0).unwrap();
+
+        let a = thread::Builder::new()
+            .name("a".to_owned())
+            .spawn(move || {
+                let _ = from_b.receive();
+                for value in 0..3 {
+                    to_b.send(value).unwrap();
+                }
+                drop(from_b);
+            })
+            .unwrap();
+        let b = thread::Builder::new()
+            .name("b".to_owned())
+            .spawn(move || {
+                let _ = from_a.receive();
+                for value in 0..3 {
+                    to_a.send(value).unwrap();
+                }
+                drop(from_a);
+            })
+            .unwrap();
+
+        let results = [a.join(), b.join()];
+
+        assert!(results.iter().any(Result::is_err));
+    }
+
+    #[test]
+    fn ignores_threads_that_dropped_their_sender() {
+        let (mut to_main, mut from_a) = sync_channel::<u32>(1);
+        let (mut to_a, mut from_main) = sync_channel::<u32>(1);
+        let later = to_main.clone();
+
+        // `a` sends once and drops its sender, then waits on `main`.
+        let a = thread::spawn(move || {
+            let _ = from_main.receive();
+            to_main.send(1).unwrap();
+            drop(to_main);
+            from_main.receive()
+        });
+
+        to_a.send(0).unwrap();
+        assert_eq!(from_a.receive(), Some(1));
+
+        // Only `c` can release `main` now, and it isn't blocked.
+        let c = thread::spawn(move || {
+            let mut later = later;
+            thread::sleep(Duration::from_millis(20));
+            later.send(2).unwrap();
+        });
+        assert_eq!(from_a.receive(), Some(2));
+
+        drop(to_a);
+        assert_eq!(a.join().unwrap(), None);
+        c.join().unwrap();
+    }
+
+    #[test]
+    fn forgets_exited_threads() {
+        let (mut sender, mut receiver) = sync_channel::<u32>(1);
+
+        let worker = thread::spawn(move || {
+            sender.send(1).unwrap();
+            thread::current().id()
+        });
+        let id = worker.join().unwrap();
+        assert_eq!(receiver.receive(), Some(1));
+
+        let registry = registry().lock().unwrap();
+        assert!(!registry.names.contains_key(&id));
+        assert!(registry
+            .handles
+            .values()
+            .flat_map(HashMap::values)
+            .all(|owner| owner.thread != Some(id)));
+    }
+}
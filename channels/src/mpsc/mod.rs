@@ -1 +1,4 @@
+#[cfg(feature = "deadlock-detection")]
+mod deadlock;
+#[allow(clippy::module_inception)]
 pub mod mpsc;
@@ -1,22 +1,61 @@
 use std::{
     collections::VecDeque,
-    fmt::Debug,
+    fmt,
     sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "deadlock-detection")]
+use super::deadlock::{self, Op};
+
+/// The receiver is gone; the value is handed back.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Like std's, so `unwrap` works for values that aren't `Debug`.
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
 /// Sender
 pub struct Sender<T> {
     shared: Arc<Shared<T>>,
+    #[cfg(feature = "deadlock-detection")]
+    handle: deadlock::Handle,
 }
 
 impl<T> Sender<T> {
-    pub fn send(&mut self, value: T) {
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
         let mut inner = self.shared.inner.lock().unwrap();
+        if inner.closed {
+            return Err(SendError(value));
+        }
         inner.queue.push_back(value);
 
+        #[cfg(feature = "deadlock-detection")]
+        {
+            self.handle.used();
+            deadlock::woke(self.shared.id(), Op::Receive);
+        }
+
         drop(inner);
 
         self.shared.receivers_available.notify_one();
+        Ok(())
     }
 }
 
@@ -28,6 +67,8 @@ impl<T> Clone for Sender<T> {
 
         Self {
             shared: Arc::clone(&self.shared),
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(self.shared.id(), Op::Send),
         }
     }
 }
@@ -39,6 +80,11 @@ impl<T> Drop for Sender<T> {
 
         let senders = inner.senders;
 
+        #[cfg(feature = "deadlock-detection")]
+        if senders == 0 {
+            deadlock::woke(self.shared.id(), Op::Receive);
+        }
+
         drop(inner);
 
         if senders == 0 {
@@ -51,26 +97,70 @@ impl<T> Drop for Sender<T> {
 pub struct SyncSender<T> {
     shared: Arc<Shared<T>>,
     capacity: usize,
+    #[cfg(feature = "deadlock-detection")]
+    handle: deadlock::Handle,
 }
 
-impl<T: Debug> SyncSender<T> {
-    pub fn send(&mut self, value: T) {
+impl<T> SyncSender<T> {
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
         loop {
             let mut inner = self.shared.inner.lock().unwrap();
-            if inner.queue.len() == self.capacity {
-                println!("<<< Queue is full {:?} >>>", value);
+            if inner.closed {
+                return Err(SendError(value));
+            } else if inner.queue.len() == self.capacity {
+                #[cfg(feature = "deadlock-detection")]
+                if let Err(report) = deadlock::block(self.shared.id(), Op::Send) {
+                    drop(inner);
+                    panic!("{report}");
+                }
+
                 let _unused = self.shared.capacity_available.wait(inner).unwrap();
+
+                #[cfg(feature = "deadlock-detection")]
+                deadlock::unblock();
             } else {
-                println!("<<< Queue has space {:?} >>>", value);
                 inner.queue.push_back(value);
 
+                #[cfg(feature = "deadlock-detection")]
+                {
+                    self.handle.used();
+                    deadlock::woke(self.shared.id(), Op::Receive);
+                }
+
                 drop(inner);
 
                 self.shared.receivers_available.notify_one();
-                break;
+                return Ok(());
             }
         }
     }
+
+    pub fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        let mut inner = self.shared.inner.lock().unwrap();
+        if inner.closed {
+            return Err(TrySendError::Disconnected(value));
+        } else if inner.queue.len() == self.capacity {
+            return Err(TrySendError::Full(value));
+        }
+
+        inner.queue.push_back(value);
+
+        #[cfg(feature = "deadlock-detection")]
+        {
+            self.handle.used();
+            deadlock::woke(self.shared.id(), Op::Receive);
+        }
+
+        drop(inner);
+
+        self.shared.receivers_available.notify_one();
+        Ok(())
+    }
+
+    /// Whether the receiver is gone, so that every send would fail.
+    pub fn is_closed(&self) -> bool {
+        self.shared.inner.lock().unwrap().closed
+    }
 }
 
 impl<T> Clone for SyncSender<T> {
@@ -82,6 +172,8 @@ impl<T> Clone for SyncSender<T> {
         Self {
             shared: Arc::clone(&self.shared),
             capacity: self.capacity,
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(self.shared.id(), Op::Send),
         }
     }
 }
@@ -93,6 +185,11 @@ impl<T> Drop for SyncSender<T> {
 
         let senders = inner.senders;
 
+        #[cfg(feature = "deadlock-detection")]
+        if senders == 0 {
+            deadlock::woke(self.shared.id(), Op::Receive);
+        }
+
         drop(inner);
 
         if senders == 0 {
@@ -104,10 +201,15 @@ impl<T> Drop for SyncSender<T> {
 pub struct Receiver<T> {
     shared: Arc<Shared<T>>,
     buffer: VecDeque<T>,
+    #[cfg(feature = "deadlock-detection")]
+    handle: deadlock::Handle,
 }
 
 impl<T> Receiver<T> {
     pub fn receive(&mut self) -> Option<T> {
+        #[cfg(feature = "deadlock-detection")]
+        self.handle.used();
+
         if let Some(data) = self.buffer.pop_front() {
             return Some(data);
         }
@@ -116,19 +218,164 @@ impl<T> Receiver<T> {
             let mut inner = self.shared.inner.lock().unwrap();
             match inner.queue.pop_front() {
                 Some(data) => {
-                    if inner.queue.is_empty() == false {
+                    if !inner.queue.is_empty() {
                         std::mem::swap(&mut inner.queue, &mut self.buffer);
-                        self.shared.capacity_available.notify_all();
                     }
+
+                    #[cfg(feature = "deadlock-detection")]
+                    deadlock::woke(self.shared.id(), Op::Send);
+
+                    self.shared.capacity_available.notify_all();
                     return Some(data);
                 }
                 None if inner.senders == 0 => return None,
                 None => {
+                    #[cfg(feature = "deadlock-detection")]
+                    if let Err(report) = deadlock::block(self.shared.id(), Op::Receive) {
+                        drop(inner);
+                        panic!("{report}");
+                    }
+
                     let _unused = self.shared.receivers_available.wait(inner).unwrap();
+
+                    #[cfg(feature = "deadlock-detection")]
+                    deadlock::unblock();
+                }
+            }
+        }
+    }
+
+    /// A `timeout` too large to represent, like `Duration::MAX`, waits as long
+    /// as `receive` would.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        if let Some(data) = self.buffer.pop_front() {
+            return Ok(data);
+        }
+
+        let deadline = Instant::now().checked_add(timeout);
+
+        loop {
+            let mut inner = self.shared.inner.lock().unwrap();
+            match inner.queue.pop_front() {
+                Some(data) => {
+                    if !inner.queue.is_empty() {
+                        std::mem::swap(&mut inner.queue, &mut self.buffer);
+                    }
+
+                    #[cfg(feature = "deadlock-detection")]
+                    deadlock::woke(self.shared.id(), Op::Send);
+
+                    self.shared.capacity_available.notify_all();
+                    return Ok(data);
+                }
+                None if inner.senders == 0 => return Err(RecvTimeoutError::Disconnected),
+                None => {
+                    let Some(deadline) = deadline else {
+                        let _unused = self.shared.receivers_available.wait(inner).unwrap();
+                        continue;
+                    };
+
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+
+                    let _unused = self
+                        .shared
+                        .receivers_available
+                        .wait_timeout(inner, deadline - now)
+                        .unwrap();
                 }
             }
         }
     }
+
+    /// Returns the next value without removing it, or `None` if nothing is
+    /// queued right now.
+    ///
+    /// Takes `&mut self`, unlike the `&self` one might expect: the shared
+    /// queue can only be read under its lock, so the value is first moved
+    /// into the receiver's own buffer, where a plain reference to it can
+    /// outlive the lock.
+    pub fn peek(&mut self) -> Option<&T> {
+        self.fill_buffer();
+
+        self.buffer.front()
+    }
+
+    /// Removes the next value only if `predicate` accepts it. Never blocks.
+    pub fn recv_if(&mut self, predicate: impl FnOnce(&T) -> bool) -> Option<T> {
+        match self.peek() {
+            Some(value) if predicate(value) => self.buffer.pop_front(),
+            _ => None,
+        }
+    }
+
+    /// Drops every queued value `predicate` rejects, keeping the order of the
+    /// rest.
+    pub fn retain(&mut self, mut predicate: impl FnMut(&T) -> bool) {
+        self.buffer.retain(&mut predicate);
+
+        let mut inner = self.shared.inner.lock().unwrap();
+
+        // Rejected values may own senders of this very channel, so they are
+        // dropped only once the lock has been released.
+        let (kept, rejected): (VecDeque<T>, VecDeque<T>) = std::mem::take(&mut inner.queue)
+            .into_iter()
+            .partition(|value| predicate(value));
+        inner.queue = kept;
+        let removed = !rejected.is_empty();
+
+        #[cfg(feature = "deadlock-detection")]
+        if removed {
+            deadlock::woke(self.shared.id(), Op::Send);
+        }
+
+        drop(inner);
+        drop(rejected);
+
+        if removed {
+            self.shared.capacity_available.notify_all();
+        }
+    }
+
+    fn fill_buffer(&mut self) {
+        if !self.buffer.is_empty() {
+            return;
+        }
+
+        let mut inner = self.shared.inner.lock().unwrap();
+        if inner.queue.is_empty() {
+            return;
+        }
+        std::mem::swap(&mut inner.queue, &mut self.buffer);
+
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::woke(self.shared.id(), Op::Send);
+
+        drop(inner);
+
+        self.shared.capacity_available.notify_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.lock().unwrap();
+        inner.closed = true;
+
+        // Pending values may own senders of this very channel, so they are
+        // dropped only once the lock has been released.
+        let queue = std::mem::take(&mut inner.queue);
+
+        #[cfg(feature = "deadlock-detection")]
+        deadlock::woke(self.shared.id(), Op::Send);
+
+        drop(inner);
+        drop(queue);
+
+        self.shared.capacity_available.notify_all();
+    }
 }
 
 impl<T> Iterator for Receiver<T> {
@@ -139,9 +386,10 @@ impl<T> Iterator for Receiver<T> {
     }
 }
 
-struct Inner<T> {
-    queue: VecDeque<T>,
-    senders: usize,
+pub(crate) struct Inner<T> {
+    pub(crate) queue: VecDeque<T>,
+    pub(crate) senders: usize,
+    pub(crate) closed: bool,
 }
 
 struct Shared<T> {
@@ -150,10 +398,18 @@ struct Shared<T> {
     capacity_available: Condvar,
 }
 
+#[cfg(feature = "deadlock-detection")]
+impl<T> Shared<T> {
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
 pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let inner = Inner::<T> {
         queue: VecDeque::new(),
         senders: 1,
+        closed: false,
     };
     let shared = Shared::<T> {
         inner: Mutex::new(inner),
@@ -164,9 +420,13 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
 
     (
         Sender::<T> {
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(shared.id(), Op::Send),
             shared: shared.clone(),
         },
         Receiver::<T> {
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(shared.id(), Op::Receive),
             shared,
             buffer: VecDeque::default(),
         },
@@ -177,6 +437,7 @@ pub fn sync_channel<T>(capacity: usize) -> (SyncSender<T>, Receiver<T>) {
     let inner = Inner::<T> {
         queue: VecDeque::new(),
         senders: 1,
+        closed: false,
     };
     let shared = Shared::<T> {
         inner: Mutex::new(inner),
@@ -187,12 +448,95 @@ pub fn sync_channel<T>(capacity: usize) -> (SyncSender<T>, Receiver<T>) {
 
     (
         SyncSender::<T> {
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(shared.id(), Op::Send),
             shared: shared.clone(),
             capacity,
         },
         Receiver::<T> {
+            #[cfg(feature = "deadlock-detection")]
+            handle: deadlock::Handle::new(shared.id(), Op::Receive),
             shared,
             buffer: VecDeque::default(),
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn receive_timeout_accepts_durations_past_any_deadline() {
+        let (mut sender, mut receiver) = channel();
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.send(1).unwrap();
+        });
+
+        assert_eq!(receiver.receive_timeout(Duration::MAX), Ok(1));
+        handle.join().unwrap();
+        assert_eq!(
+            receiver.receive_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn can_peek_without_removing() {
+        let (mut sender, mut receiver) = channel();
+
+        assert_eq!(receiver.peek(), None);
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        assert_eq!(receiver.peek(), Some(&1));
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(receiver.peek(), Some(&2));
+    }
+
+    #[test]
+    fn recv_if_only_takes_matching_head() {
+        let (mut sender, mut receiver) = channel();
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        assert_eq!(receiver.recv_if(|value| *value == 2), None);
+        assert_eq!(receiver.recv_if(|value| *value == 1), Some(1));
+        assert_eq!(receiver.recv_if(|value| *value == 2), Some(2));
+        assert_eq!(receiver.recv_if(|_| true), None);
+    }
+
+    #[test]
+    fn retain_purges_buffered_and_queued_values() {
+        let (mut sender, mut receiver) = sync_channel(4);
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(receiver.peek(), Some(&1));
+        sender.send(3).unwrap();
+        sender.send(4).unwrap();
+
+        receiver.retain(|value| value % 2 == 0);
+        drop(sender);
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec![2, 4]);
+    }
+
+    #[test]
+    fn retain_drops_rejected_senders_outside_the_lock() {
+        let (mut sender, mut receiver) = channel::<Box<dyn std::any::Any + Send>>();
+        let inner = sender.clone();
+        sender.send(Box::new(inner)).unwrap();
+
+        receiver.retain(|_| false);
+        drop(sender);
+
+        assert!(receiver.receive().is_none());
+    }
+}
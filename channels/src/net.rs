@@ -0,0 +1,690 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    hash::{BuildHasher, Hasher},
+    io::{self, BufReader},
+    marker::PhantomData,
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::{read_frame, write_frame, Bincode, Codec},
+    mpsc::mpsc::{self, sync_channel, SyncSender},
+};
+
+const DATA: u8 = 0;
+const CLOSE: u8 = 1;
+
+const RECONNECT_ATTEMPTS: u32 = 10;
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(50);
+/// How long dropping the last sender waits for outstanding acknowledgements.
+const FLUSH_ON_DROP: Duration = Duration::from_secs(5);
+
+/// Sender
+///
+/// Remote end of a `Receiver` listening on a TCP address. At most `capacity`
+/// messages can be waiting for an acknowledgement, mirroring the capacity of
+/// `sync_channel`. When the connection breaks it is re-established and every
+/// unacknowledged message is sent again; the receiver drops duplicates.
+pub struct Sender<T, C = Bincode> {
+    shared: Arc<Shared>,
+    codec: C,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T, C: Codec<T>> Sender<T, C> {
+    /// Blocks while the in-flight window is full. Fails with `BrokenPipe`
+    /// once the receiver reports that it is gone. Also fails when the receiver
+    /// cannot be reached after several reconnection attempts; the message
+    /// then stays queued and is retransmitted by the next successful
+    /// reconnection.
+    pub fn send(&mut self, value: T) -> io::Result<()> {
+        let payload = self.codec.encode(&value)?;
+
+        let mut link = self.shared.link.lock().unwrap();
+        loop {
+            if link.receiver_gone {
+                return Err(receiver_gone());
+            } else if link.stream.is_none() {
+                link = self.shared.reconnect(link)?;
+            } else if link.in_flight.len() < self.shared.capacity {
+                break;
+            } else {
+                link = self.shared.acked.wait(link).unwrap();
+            }
+        }
+
+        link.next_seq += 1;
+        let seq = link.next_seq;
+        let frame = data_frame(seq, &payload);
+        link.in_flight.push_back(frame);
+
+        let written = link.write_last();
+        if written.is_err() {
+            link.disconnect();
+            let _unused = self.shared.reconnect(link)?;
+        }
+
+        Ok(())
+    }
+
+    /// Blocks until every message sent so far has been acknowledged.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.shared.flush(None).map(|_| ())
+    }
+}
+
+impl<T, C: Clone> Clone for Sender<T, C> {
+    fn clone(&self) -> Self {
+        let mut link = self.shared.link.lock().unwrap();
+        link.senders += 1;
+        drop(link);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+            codec: self.codec.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C> Drop for Sender<T, C> {
+    fn drop(&mut self) {
+        let mut link = self.shared.link.lock().unwrap();
+        link.senders -= 1;
+
+        let senders = link.senders;
+
+        drop(link);
+
+        if senders == 0 {
+            // Even if the flush gives up, the receiver is told the session is
+            // over and the socket is shut down, which also ends the thread
+            // reading acknowledgements.
+            let deadline = Instant::now() + FLUSH_ON_DROP;
+            let mut link = match self.shared.flush(Some(deadline)) {
+                Ok(link) => link,
+                Err(_) => self.shared.link.lock().unwrap(),
+            };
+            if let Some(stream) = link.stream.as_mut() {
+                let _ = write_frame(stream, &[CLOSE]);
+            }
+            link.disconnect();
+        }
+    }
+}
+
+struct Link {
+    stream: Option<TcpStream>,
+    in_flight: VecDeque<Vec<u8>>,
+    next_seq: u64,
+    generation: u64,
+    senders: usize,
+    /// Set once the receiver answered with `CLOSE`; nothing will be
+    /// acknowledged any more.
+    receiver_gone: bool,
+}
+
+impl Link {
+    /// Shuts the socket down rather than just dropping it, so the cloned
+    /// handle used by the acknowledgement thread is released too.
+    fn disconnect(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn write_last(&mut self) -> io::Result<()> {
+        let (Some(stream), Some(frame)) = (self.stream.as_mut(), self.in_flight.back()) else {
+            return Err(io::ErrorKind::NotConnected.into());
+        };
+
+        write_frame(stream, frame)
+    }
+}
+
+struct Shared {
+    addr: SocketAddr,
+    session: u64,
+    capacity: usize,
+    link: Mutex<Link>,
+    acked: Condvar,
+}
+
+impl Shared {
+    /// Waits until nothing is in flight, giving up at `deadline` if any.
+    fn flush(self: &Arc<Self>, deadline: Option<Instant>) -> io::Result<MutexGuard<'_, Link>> {
+        let mut link = self.link.lock().unwrap();
+        loop {
+            let now = Instant::now();
+
+            if link.receiver_gone {
+                return Err(receiver_gone());
+            } else if link.in_flight.is_empty() {
+                return Ok(link);
+            } else if deadline.is_some_and(|deadline| now >= deadline) {
+                return Err(io::ErrorKind::TimedOut.into());
+            } else if link.stream.is_none() {
+                link = self.reconnect(link)?;
+            } else if let Some(deadline) = deadline {
+                link = self.acked.wait_timeout(link, deadline - now).unwrap().0;
+            } else {
+                link = self.acked.wait(link).unwrap();
+            }
+        }
+    }
+
+    fn reconnect<'a>(
+        self: &'a Arc<Self>,
+        mut link: MutexGuard<'a, Link>,
+    ) -> io::Result<MutexGuard<'a, Link>> {
+        let mut last_error = io::ErrorKind::NotConnected.into();
+
+        for attempt in 0..RECONNECT_ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(RECONNECT_BACKOFF * attempt);
+            }
+
+            match self.connect(&mut link) {
+                Ok(()) => return Ok(link),
+                Err(err) => last_error = err,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn connect(self: &Arc<Self>, link: &mut Link) -> io::Result<()> {
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.set_nodelay(true)?;
+
+        write_frame(&mut stream, &self.session.to_be_bytes())?;
+        for frame in &link.in_flight {
+            write_frame(&mut stream, frame)?;
+        }
+
+        let acks = stream.try_clone()?;
+        link.generation += 1;
+        link.stream = Some(stream);
+
+        let generation = link.generation;
+        let shared = Arc::clone(self);
+        thread::spawn(move || shared.read_acks(acks, generation));
+
+        Ok(())
+    }
+
+    fn read_acks(&self, stream: TcpStream, generation: u64) {
+        let mut stream = BufReader::new(stream);
+
+        loop {
+            let frame = read_frame(&mut stream).ok().flatten();
+
+            let mut link = self.link.lock().unwrap();
+            if link.generation != generation {
+                return;
+            }
+
+            match frame.as_deref() {
+                Some(&[CLOSE]) => {
+                    link.receiver_gone = true;
+                    link.in_flight.clear();
+                }
+                Some(ack) if ack.len() == 8 => {
+                    let seq = u64::from_be_bytes(ack.try_into().unwrap());
+
+                    // Acknowledgements are cumulative and in-flight frames are
+                    // kept in sequence order.
+                    let oldest = link.next_seq + 1 - link.in_flight.len() as u64;
+                    let acked =
+                        ((seq + 1).saturating_sub(oldest) as usize).min(link.in_flight.len());
+                    link.in_flight.drain(..acked);
+
+                    drop(link);
+                    self.acked.notify_all();
+                    continue;
+                }
+                _ => {}
+            }
+
+            link.disconnect();
+            drop(link);
+            self.acked.notify_all();
+            return;
+        }
+    }
+}
+
+fn receiver_gone() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "receiver is gone")
+}
+
+fn data_frame(seq: u64, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(9 + payload.len());
+    frame.push(DATA);
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Receiver
+///
+/// Accepts connections from any number of remote senders for as long as it
+/// is alive. Like `mpsc::Receiver`, `receive` returns `None` once every
+/// sender that connected so far has been dropped; senders connecting after
+/// that are served by later calls.
+pub struct Receiver<T> {
+    receiver: mpsc::Receiver<T>,
+    sessions: Arc<Mutex<Sessions<T>>>,
+    local_addr: SocketAddr,
+}
+
+impl<T> Receiver<T> {
+    pub fn receive(&mut self) -> Option<T> {
+        loop {
+            if let Some(value) = self.receiver.receive() {
+                return Some(value);
+            }
+
+            let mut sessions = self.sessions.lock().unwrap();
+            let reopened = sessions.reopened.pop_front()?;
+            drop(sessions);
+
+            self.receiver = reopened;
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.receiving = false;
+
+        let template = sessions.template.take();
+        let reopened = std::mem::take(&mut sessions.reopened);
+        drop(sessions);
+
+        drop(template);
+        drop(reopened);
+
+        // The accept loop only notices once another connection comes in.
+        let mut addr = self.local_addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        let _ = TcpStream::connect(addr);
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+struct Session<T> {
+    last_seq: u64,
+    sender: SyncSender<T>,
+}
+
+struct Sessions<T> {
+    /// Cloned for every new session. Dropped while no session is open, so
+    /// the receiver sees the disconnect.
+    template: Option<SyncSender<T>>,
+    /// Channels opened by sessions that arrived after such a disconnect, in
+    /// the order the receiver has to drain them.
+    reopened: VecDeque<mpsc::Receiver<T>>,
+    sessions: HashMap<u64, Session<T>>,
+    capacity: usize,
+    receiving: bool,
+}
+
+/// Connects to the receiver at `addr`. `capacity` bounds the number of
+/// messages in flight.
+pub fn connect<T, A: ToSocketAddrs>(addr: A, capacity: usize) -> io::Result<Sender<T>>
+where
+    Bincode: Codec<T>,
+{
+    connect_with_codec(addr, capacity, Bincode)
+}
+
+pub fn connect_with_codec<T, C, A>(addr: A, capacity: usize, codec: C) -> io::Result<Sender<T, C>>
+where
+    C: Codec<T>,
+    A: ToSocketAddrs,
+{
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or(io::ErrorKind::AddrNotAvailable)?;
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+
+    let shared = Arc::new(Shared {
+        addr,
+        session: hasher.finish(),
+        capacity: capacity.max(1),
+        link: Mutex::new(Link {
+            stream: None,
+            in_flight: VecDeque::new(),
+            next_seq: 0,
+            generation: 0,
+            senders: 1,
+            receiver_gone: false,
+        }),
+        acked: Condvar::default(),
+    });
+
+    let mut link = shared.link.lock().unwrap();
+    shared.connect(&mut link)?;
+    drop(link);
+
+    Ok(Sender {
+        shared,
+        codec,
+        _marker: PhantomData,
+    })
+}
+
+/// Listens on `addr`. Up to `capacity` received messages are buffered before
+/// acknowledgements stop and remote senders block.
+pub fn listen<T, A: ToSocketAddrs>(addr: A, capacity: usize) -> io::Result<Receiver<T>>
+where
+    T: Send + 'static,
+    Bincode: Codec<T>,
+{
+    listen_with_codec(addr, capacity, Bincode)
+}
+
+pub fn listen_with_codec<T, C, A>(addr: A, capacity: usize, codec: C) -> io::Result<Receiver<T>>
+where
+    T: Send + 'static,
+    C: Codec<T> + Clone + Send + 'static,
+    A: ToSocketAddrs,
+{
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    let capacity = capacity.max(1);
+    let (template, receiver) = sync_channel(capacity);
+    let sessions = Arc::new(Mutex::new(Sessions {
+        template: Some(template),
+        reopened: VecDeque::new(),
+        sessions: HashMap::new(),
+        capacity,
+        receiving: true,
+    }));
+
+    let accepting = Arc::clone(&sessions);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sessions = Arc::clone(&accepting);
+            let codec = codec.clone();
+            thread::spawn(move || serve(stream, sessions, codec));
+
+            // The connection that finds the receiver gone is still served, to
+            // tell the sender so; later ones are refused.
+            if !accepting.lock().unwrap().receiving {
+                break;
+            }
+        }
+    });
+
+    Ok(Receiver {
+        receiver,
+        sessions,
+        local_addr,
+    })
+}
+
+fn serve<T, C: Codec<T>>(stream: TcpStream, sessions: Arc<Mutex<Sessions<T>>>, codec: C) {
+    let Ok(mut acks) = stream.try_clone() else {
+        return;
+    };
+    let mut stream = BufReader::new(stream);
+
+    let Some(session) = read_frame(&mut stream)
+        .ok()
+        .flatten()
+        .and_then(|frame| frame.try_into().ok())
+        .map(u64::from_be_bytes)
+    else {
+        return;
+    };
+
+    let mut guard = sessions.lock().unwrap();
+    let Some(mut sender) = guard.sender_for(session) else {
+        drop(guard);
+        let _ = write_frame(&mut acks, &[CLOSE]);
+        return;
+    };
+    drop(guard);
+
+    while let Ok(Some(frame)) = read_frame(&mut stream) {
+        match frame.split_first() {
+            Some((&DATA, rest)) if rest.len() >= 8 => {
+                let (seq, payload) = rest.split_at(8);
+                let seq = u64::from_be_bytes(seq.try_into().unwrap());
+
+                let mut guard = sessions.lock().unwrap();
+                let fresh = guard.advance(session, seq);
+                drop(guard);
+
+                if fresh {
+                    let Ok(value) = codec.decode(payload) else {
+                        return;
+                    };
+                    if sender.send(value).is_err() {
+                        let _ = write_frame(&mut acks, &[CLOSE]);
+                        return;
+                    }
+                }
+
+                if write_frame(&mut acks, &seq.to_be_bytes()).is_err() {
+                    return;
+                }
+            }
+            Some((&CLOSE, _)) => {
+                let mut guard = sessions.lock().unwrap();
+                let closed = guard.close(session);
+                drop(guard);
+                drop(closed);
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+impl<T> Sessions<T> {
+    /// `None` once the receiver is gone.
+    fn sender_for(&mut self, session: u64) -> Option<SyncSender<T>> {
+        if !self.receiving {
+            return None;
+        } else if let Some(existing) = self.sessions.get(&session) {
+            return Some(existing.sender.clone());
+        }
+
+        let template = self.template.get_or_insert_with(|| {
+            let (template, receiver) = sync_channel(self.capacity);
+            self.reopened.push_back(receiver);
+            template
+        });
+        let sender = template.clone();
+        self.sessions.insert(
+            session,
+            Session {
+                last_seq: 0,
+                sender: sender.clone(),
+            },
+        );
+
+        Some(sender)
+    }
+
+    /// Records `seq` as delivered. Returns `false` for retransmissions.
+    fn advance(&mut self, session: u64, seq: u64) -> bool {
+        match self.sessions.get_mut(&session) {
+            Some(state) if seq > state.last_seq => {
+                state.last_seq = seq;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets `session`, returning the senders that must be dropped once
+    /// the lock is released.
+    fn close(&mut self, session: u64) -> Vec<SyncSender<T>> {
+        let mut closed: Vec<_> = self
+            .sessions
+            .remove(&session)
+            .map(|state| state.sender)
+            .into_iter()
+            .collect();
+
+        if self.sessions.is_empty() {
+            closed.extend(self.template.take());
+        }
+
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::iter;
+
+    #[test]
+    fn can_send_over_loopback() {
+        let receiver = listen::<u32, _>("127.0.0.1:0", 4).unwrap();
+        let mut sender = connect::<u32, _>(receiver.local_addr(), 2).unwrap();
+
+        let handle = thread::spawn(move || {
+            for value in 0..100 {
+                sender.send(value).unwrap();
+            }
+        });
+
+        let received: Vec<u32> = receiver.collect();
+        handle.join().unwrap();
+
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cloned_senders_share_the_session() {
+        let receiver = listen::<String, _>("127.0.0.1:0", 4).unwrap();
+        let mut sender = connect::<String, _>(receiver.local_addr(), 4).unwrap();
+        let mut other = sender.clone();
+
+        sender.send("a".to_owned()).unwrap();
+        drop(sender);
+        other.send("b".to_owned()).unwrap();
+        drop(other);
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn accepts_senders_after_the_last_one_left() {
+        let mut receiver = listen::<u32, _>("127.0.0.1:0", 4).unwrap();
+
+        let mut first = connect::<u32, _>(receiver.local_addr(), 2).unwrap();
+        first.send(1).unwrap();
+        drop(first);
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(receiver.receive(), None);
+
+        let mut second = connect::<u32, _>(receiver.local_addr(), 2).unwrap();
+        second.send(2).unwrap();
+        drop(second);
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn send_fails_once_the_receiver_is_gone() {
+        let receiver = listen::<u32, _>("127.0.0.1:0", 4).unwrap();
+        let mut sender = connect::<u32, _>(receiver.local_addr(), 2).unwrap();
+        drop(receiver);
+
+        let result = (0..100).try_for_each(|value| sender.send(value));
+
+        assert!(result.is_err());
+        assert!(sender.flush().is_err());
+    }
+
+    #[test]
+    fn dropping_the_sender_closes_without_acknowledgements() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = connect::<u32, _>(listener.local_addr().unwrap(), 4).unwrap();
+        sender.send(1).unwrap();
+
+        // Nothing is ever acknowledged, so the flush on drop gives up.
+        let (stream, _) = listener.accept().unwrap();
+        drop(sender);
+
+        let mut stream = BufReader::new(stream);
+        let frames: Vec<_> = iter::from_fn(|| read_frame(&mut stream).ok().flatten()).collect();
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2], [CLOSE]);
+    }
+
+    #[test]
+    fn dropping_the_receiver_releases_its_port() {
+        let receiver = listen::<u32, _>("127.0.0.1:0", 4).unwrap();
+        let addr = receiver.local_addr();
+        drop(receiver);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while TcpListener::bind(addr).is_err() {
+            assert!(Instant::now() < deadline, "{addr} is still bound");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn reconnects_and_retransmits_without_duplicates() {
+        let mut receiver = listen::<u32, _>("127.0.0.1:0", 16).unwrap();
+        let mut sender = connect::<u32, _>(receiver.local_addr(), 8).unwrap();
+
+        for value in 0..5 {
+            sender.send(value).unwrap();
+        }
+
+        let link = sender.shared.link.lock().unwrap();
+        link.stream
+            .as_ref()
+            .unwrap()
+            .shutdown(Shutdown::Both)
+            .unwrap();
+        drop(link);
+
+        for value in 5..10 {
+            sender.send(value).unwrap();
+        }
+        sender.flush().unwrap();
+
+        let received: Vec<u32> = (0..10).map(|_| receiver.receive().unwrap()).collect();
+        drop(sender);
+
+        assert_eq!(received, (0..10).collect::<Vec<_>>());
+        assert_eq!(receiver.receive(), None);
+    }
+}
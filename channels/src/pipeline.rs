@@ -0,0 +1,366 @@
+use std::{
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use crate::mpsc::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Pipeline
+///
+/// A chain of stages where every stage runs on its own thread(s) and is
+/// connected to the next one by a bounded `sync_channel`, so a slow stage
+/// applies backpressure all the way up to the source.
+pub struct Pipeline<T> {
+    receiver: Receiver<T>,
+    capacity: usize,
+    handles: Vec<JoinHandle<()>>,
+}
+
+/// Starts a pipeline fed by `iter`. Every channel between stages holds at
+/// most `capacity` items.
+pub fn source<I>(iter: I, capacity: usize) -> Pipeline<I::Item>
+where
+    I: IntoIterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    let (mut sender, receiver) = sync_channel(capacity);
+
+    let handle = thread::spawn(move || {
+        for item in iter {
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+    });
+
+    Pipeline {
+        receiver,
+        capacity,
+        handles: vec![handle],
+    }
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Applies `f` on `workers` threads. Output keeps the input order.
+    pub fn map<U, F>(self, f: F, workers: usize) -> Pipeline<U>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> U + Send + Sync + 'static,
+    {
+        let capacity = self.capacity;
+        let (sender, mut receiver) = sync_channel(capacity);
+        // One slot per item between taking a sequence number and being
+        // forwarded, so a slow item can't let `pending` grow without bound.
+        let (window, mut forwarded) = sync_channel(capacity);
+        let mut handles = self.map_workers(f, workers, sender, Some(window));
+
+        let (mut output, ordered) = sync_channel(capacity);
+
+        // Workers finish out of order; results are parked here until every
+        // earlier sequence number has been forwarded.
+        handles.push(thread::spawn(move || {
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+
+            while let Some((seq, result)) = receiver.receive() {
+                pending.insert(seq, result);
+
+                while let Some(result) = pending.remove(&next) {
+                    let value = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+                    if output.send(value).is_err() {
+                        return;
+                    }
+                    let _ = forwarded.receive();
+                    next += 1;
+                }
+            }
+        }));
+
+        Pipeline {
+            receiver: ordered,
+            capacity,
+            handles,
+        }
+    }
+
+    /// Applies `f` on `workers` threads. Output is emitted as soon as each
+    /// item is done, in no particular order.
+    pub fn map_unordered<U, F>(self, f: F, workers: usize) -> Pipeline<U>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> U + Send + Sync + 'static,
+    {
+        let capacity = self.capacity;
+        let (sender, mut receiver) = sync_channel(capacity);
+        let mut handles = self.map_workers(f, workers, sender, None);
+
+        let (mut output, unordered) = sync_channel(capacity);
+
+        handles.push(thread::spawn(move || {
+            while let Some((_, result)) = receiver.receive() {
+                let value = result.unwrap_or_else(|payload| panic::resume_unwind(payload));
+                if output.send(value).is_err() {
+                    return;
+                }
+            }
+        }));
+
+        Pipeline {
+            receiver: unordered,
+            capacity,
+            handles,
+        }
+    }
+
+    pub fn filter<F>(self, f: F) -> Pipeline<T>
+    where
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        self.stage(move |mut receiver, mut sender| {
+            while let Some(value) = receiver.receive() {
+                if f(&value) && sender.send(value).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Groups items into vectors of `size`. The last batch may be shorter.
+    pub fn batch(self, size: usize) -> Pipeline<Vec<T>> {
+        self.stage(move |mut receiver, mut sender| {
+            let mut batch = Vec::with_capacity(size);
+
+            while let Some(value) = receiver.receive() {
+                batch.push(value);
+
+                if batch.len() == size {
+                    let full = std::mem::replace(&mut batch, Vec::with_capacity(size));
+                    if sender.send(full).is_err() {
+                        return;
+                    }
+                }
+            }
+
+            if !batch.is_empty() {
+                let _ = sender.send(batch);
+            }
+        })
+    }
+
+    /// Consumes every item with `f` on its own thread and waits for the whole
+    /// pipeline to finish. Fails if any stage panicked.
+    pub fn sink<F>(self, mut f: F) -> thread::Result<()>
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        let mut receiver = self.receiver;
+        let mut handles = self.handles;
+
+        handles.push(thread::spawn(move || {
+            while let Some(value) = receiver.receive() {
+                f(value);
+            }
+        }));
+
+        let mut result = Ok(());
+        for handle in handles {
+            result = result.and(handle.join());
+        }
+
+        result
+    }
+
+    fn stage<U, F>(self, f: F) -> Pipeline<U>
+    where
+        U: Send + 'static,
+        F: FnOnce(Receiver<T>, SyncSender<U>) + Send + 'static,
+    {
+        let (sender, receiver) = sync_channel(self.capacity);
+        let input = self.receiver;
+        let mut handles = self.handles;
+
+        handles.push(thread::spawn(move || f(input, sender)));
+
+        Pipeline {
+            receiver,
+            capacity: self.capacity,
+            handles,
+        }
+    }
+
+    fn map_workers<U, F>(
+        self,
+        f: F,
+        workers: usize,
+        sender: SyncSender<(u64, thread::Result<U>)>,
+        window: Option<SyncSender<()>>,
+    ) -> Vec<JoinHandle<()>>
+    where
+        U: Send + 'static,
+        F: Fn(T) -> U + Send + Sync + 'static,
+    {
+        // Sequence numbers are handed out while holding the input lock so they
+        // match the order items left the previous stage. Waiting for a slot in
+        // `window` under that lock is fine: nobody else may take one anyway.
+        // A panic in `f` is passed on with its sequence number rather than
+        // ending the worker, so the stage after it is not left waiting for
+        // an item that never comes; it re-raises the panic instead.
+        let input = Arc::new(Mutex::new((self.receiver, 0u64)));
+        let f = Arc::new(f);
+        let mut handles = self.handles;
+
+        for _ in 0..workers.max(1) {
+            let input = Arc::clone(&input);
+            let f = Arc::clone(&f);
+            let mut sender = sender.clone();
+            let mut window = window.clone();
+
+            handles.push(thread::spawn(move || loop {
+                let mut guard = input.lock().unwrap();
+                let (receiver, next) = &mut *guard;
+                let Some(value) = receiver.receive() else {
+                    break;
+                };
+                if let Some(window) = window.as_mut() {
+                    if window.send(()).is_err() {
+                        break;
+                    }
+                }
+                let seq = *next;
+                *next += 1;
+                drop(guard);
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| f(value)));
+                if sender.send((seq, result)).is_err() {
+                    break;
+                }
+            }));
+        }
+
+        handles
+    }
+}
+
+impl<T> Iterator for Pipeline<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use crate::mpsc::mpsc::{channel, RecvTimeoutError};
+
+    #[test]
+    fn can_compose_stages() {
+        let (mut sender, receiver) = sync_channel(16);
+
+        source(1..=10, 2)
+            .map(|value| value * 2, 3)
+            .filter(|value| value % 4 == 0)
+            .batch(2)
+            .sink(move |batch| sender.send(batch).unwrap())
+            .unwrap();
+
+        let batches: Vec<Vec<i32>> = receiver.collect();
+
+        assert_eq!(batches, vec![vec![4, 8], vec![12, 16], vec![20]]);
+    }
+
+    #[test]
+    fn map_preserves_order() {
+        let result: Vec<u64> = source(0..50u64, 4)
+            .map(
+                |value| {
+                    thread::sleep(Duration::from_micros((50 - value) * 20));
+                    value
+                },
+                8,
+            )
+            .collect();
+
+        assert_eq!(result, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn map_bounds_items_waiting_on_a_slow_one() {
+        let started = Arc::new(AtomicUsize::new(0));
+
+        let in_flight = source(0..100, 2)
+            .map(
+                move |value| {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    if value == 0 {
+                        thread::sleep(Duration::from_millis(20));
+                    }
+                    started.load(Ordering::SeqCst)
+                },
+                4,
+            )
+            .next();
+
+        assert!(in_flight.unwrap() <= 2);
+    }
+
+    #[test]
+    fn stages_stop_once_the_pipeline_is_dropped() {
+        let (alive, mut finished) = channel::<()>();
+
+        let mut pipeline = source(
+            (0..).inspect(move |_| {
+                let _ = &alive;
+            }),
+            2,
+        )
+        .map(|value| value + 1, 2)
+        .filter(|_| true);
+
+        assert_eq!(pipeline.next(), Some(1));
+        drop(pipeline);
+
+        assert_eq!(
+            finished.receive_timeout(Duration::from_secs(5)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn map_unordered_yields_every_item() {
+        let mut result: Vec<i32> = source(0..100, 1)
+            .map_unordered(|value| value + 1, 4)
+            .collect();
+        result.sort();
+
+        assert_eq!(result, (1..=100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sink_reports_panicked_map() {
+        let result = source(0..100, 2)
+            .map(|value| if value == 3 { panic!("boom") } else { value }, 4)
+            .sink(|_| {});
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn sink_reports_panicked_stage() {
+        let result = source(0..3, 1)
+            .filter(|value| if *value == 2 { panic!("boom") } else { true })
+            .sink(|_| {});
+
+        assert!(result.is_err());
+    }
+}
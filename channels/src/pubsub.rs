@@ -0,0 +1,184 @@
+use std::sync::{Arc, Mutex};
+
+use crate::mpsc::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+
+/// Message
+///
+/// A published value together with the concrete topic it was published to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message<T> {
+    pub topic: String,
+    pub value: T,
+}
+
+struct Subscription<T> {
+    pattern: Vec<String>,
+    sender: SyncSender<Message<T>>,
+}
+
+/// Broker
+///
+/// Routes published values to every subscriber whose pattern matches the
+/// topic. Topics are dot-separated; in patterns `*` matches exactly one
+/// segment and a trailing `#` matches any number of remaining segments, so
+/// `orders.*` matches `orders.created` but not `orders.eu.created`, while
+/// `orders.#` matches both.
+pub struct Broker<T> {
+    subscriptions: Arc<Mutex<Vec<Subscription<T>>>>,
+}
+
+impl<T: Clone> Broker<T> {
+    pub fn new() -> Self {
+        Self {
+            subscriptions: Arc::default(),
+        }
+    }
+
+    /// Each subscriber gets its own queue holding up to `capacity` messages.
+    /// Dropping the receiver unsubscribes it.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` has a `#` anywhere but in its last segment.
+    pub fn subscribe(&self, pattern: &str, capacity: usize) -> Receiver<Message<T>> {
+        let pattern: Vec<String> = pattern.split('.').map(str::to_owned).collect();
+        assert!(
+            !pattern.iter().rev().skip(1).any(|segment| segment == "#"),
+            "`#` may only be the last segment of a pattern"
+        );
+
+        let (sender, receiver) = sync_channel(capacity);
+
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .push(Subscription { pattern, sender });
+
+        receiver
+    }
+
+    /// Delivers `value` to every matching subscriber without blocking and
+    /// returns how many received it. Subscribers whose queue is full miss the
+    /// message.
+    pub fn publish(&self, topic: &str, value: T) -> usize {
+        let topic_segments: Vec<&str> = topic.split('.').collect();
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut delivered = 0;
+
+        subscriptions.retain_mut(|subscription| {
+            if !matches(&subscription.pattern, &topic_segments) {
+                return true;
+            }
+
+            let message = Message {
+                topic: topic.to_owned(),
+                value: value.clone(),
+            };
+
+            match subscription.sender.try_send(message) {
+                Ok(()) => {
+                    delivered += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+
+        delivered
+    }
+
+    /// Subscribers whose receiver is still alive.
+    pub fn subscribers(&self) -> usize {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|subscription| !subscription.sender.is_closed());
+
+        subscriptions.len()
+    }
+}
+
+impl<T: Clone> Default for Broker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Broker<T> {
+    fn clone(&self) -> Self {
+        Self {
+            subscriptions: Arc::clone(&self.subscriptions),
+        }
+    }
+}
+
+fn matches(pattern: &[String], topic: &[&str]) -> bool {
+    match (pattern.split_first(), topic.split_first()) {
+        (Some((head, _)), _) if head == "#" => true,
+        (Some((head, pattern)), Some((segment, topic))) if head == "*" || head == segment => {
+            matches(pattern, topic)
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_by_wildcard_pattern() {
+        let broker = Broker::new();
+        let mut all = broker.subscribe("orders.#", 8);
+        let mut direct = broker.subscribe("orders.*", 8);
+        let mut created = broker.subscribe("orders.created", 8);
+
+        assert_eq!(broker.publish("orders.created", 1), 3);
+        assert_eq!(broker.publish("orders.eu.shipped", 2), 1);
+        assert_eq!(broker.publish("payments.created", 3), 0);
+        drop(broker);
+
+        assert_eq!(
+            all.by_ref()
+                .map(|message| message.value)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            direct.receive().map(|message| message.topic),
+            Some("orders.created".to_owned())
+        );
+        assert_eq!(direct.receive(), None);
+        assert_eq!(created.receive().map(|message| message.value), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "last segment")]
+    fn rejects_hash_before_the_last_segment() {
+        Broker::<u32>::new().subscribe("orders.#.created", 4);
+    }
+
+    #[test]
+    fn full_subscriber_misses_messages() {
+        let broker = Broker::new();
+        let mut slow = broker.subscribe("ticks", 1);
+
+        assert_eq!(broker.publish("ticks", 1), 1);
+        assert_eq!(broker.publish("ticks", 2), 0);
+
+        assert_eq!(slow.receive().map(|message| message.value), Some(1));
+    }
+
+    #[test]
+    fn unsubscribes_on_receiver_drop() {
+        let broker = Broker::new();
+        let receiver = broker.subscribe("orders.*", 4);
+        let _other = broker.subscribe("payments.*", 4);
+        assert_eq!(broker.subscribers(), 2);
+
+        drop(receiver);
+
+        assert_eq!(broker.subscribers(), 1);
+        assert_eq!(broker.publish("orders.created", "order"), 0);
+    }
+}
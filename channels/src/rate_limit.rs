@@ -0,0 +1,187 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{mpsc::mpsc::SendError, traits::SendChannel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    RateLimited(T),
+    Disconnected(T),
+}
+
+/// TokenBucket
+///
+/// Refills at `rate` tokens per second up to `burst` tokens. Every message
+/// costs one token.
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+
+    /// Takes a token, or returns how long to wait until one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        self.refill(Instant::now());
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // A tiny rate can put the next token further away than a
+            // `Duration` reaches, which is as good as never.
+            let wait = (1.0 - self.tokens) / self.rate;
+            Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+        }
+    }
+}
+
+/// RateLimitedSender
+///
+/// Wraps any sender with a token bucket. Clones share the same bucket, so the
+/// configured rate is a global budget across every thread holding a clone.
+pub struct RateLimitedSender<S> {
+    sender: S,
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl<S> RateLimitedSender<S> {
+    /// `rate` is in messages per second and `burst` is how many messages can
+    /// be sent back to back after an idle period. The bucket starts full.
+    pub fn new(sender: S, rate: f64, burst: usize) -> Self {
+        assert!(rate > 0.0, "rate must be positive");
+
+        let burst = burst.max(1) as f64;
+
+        Self {
+            sender,
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                rate,
+                burst,
+                tokens: burst,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Blocks until the budget allows another message, then sends it. Fails,
+    /// handing the value back, if the wrapped channel is disconnected.
+    pub fn send<T>(&mut self, value: T) -> Result<(), SendError<T>>
+    where
+        S: SendChannel<T>,
+    {
+        loop {
+            let wait = self.bucket.lock().unwrap().take();
+
+            match wait {
+                Ok(()) => break,
+                Err(wait) => thread::sleep(wait),
+            }
+        }
+
+        self.sender.send(value)
+    }
+
+    /// Sends only if the budget allows it right now, otherwise hands the value
+    /// back.
+    pub fn try_send<T>(&mut self, value: T) -> Result<(), TrySendError<T>>
+    where
+        S: SendChannel<T>,
+    {
+        let taken = self.bucket.lock().unwrap().take();
+
+        match taken {
+            Ok(()) => self
+                .sender
+                .send(value)
+                .map_err(|SendError(value)| TrySendError::Disconnected(value)),
+            Err(_) => Err(TrySendError::RateLimited(value)),
+        }
+    }
+}
+
+impl<S: Clone> Clone for RateLimitedSender<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            bucket: Arc::clone(&self.bucket),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mpsc::mpsc::channel;
+
+    #[test]
+    fn rejects_when_over_budget() {
+        let (sender, receiver) = channel();
+        let mut sender = RateLimitedSender::new(sender, 1.0, 2);
+
+        assert_eq!(sender.try_send(1), Ok(()));
+        assert_eq!(sender.try_send(2), Ok(()));
+        assert_eq!(sender.try_send(3), Err(TrySendError::RateLimited(3)));
+        drop(sender);
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn clones_share_the_budget() {
+        let (sender, receiver) = channel();
+        let sender = RateLimitedSender::new(sender, 200.0, 1);
+        let started = Instant::now();
+
+        let handles: Vec<_> = (0..4)
+            .map(|id| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        sender.send(id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // 20 messages with a burst of 1 need at least 19 refills at 200/s.
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(receiver.count(), 20);
+    }
+
+    #[test]
+    fn reports_a_disconnected_channel() {
+        let (sender, receiver) = channel();
+        let mut sender = RateLimitedSender::new(sender, 1.0, 2);
+        drop(receiver);
+
+        assert_eq!(sender.try_send(1), Err(TrySendError::Disconnected(1)));
+        assert_eq!(sender.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn waits_forever_at_a_tiny_rate() {
+        let (sender, _receiver) = channel();
+        let mut sender = RateLimitedSender::new(sender, 1e-300, 1);
+
+        assert_eq!(sender.try_send(1), Ok(()));
+        assert_eq!(sender.try_send(2), Err(TrySendError::RateLimited(2)));
+        assert_eq!(sender.bucket.lock().unwrap().take(), Err(Duration::MAX));
+    }
+}
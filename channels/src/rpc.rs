@@ -0,0 +1,170 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::mpsc::mpsc::{self, RecvTimeoutError, Sender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallError {
+    /// No response arrived in time.
+    Timeout,
+    /// The server is gone or dropped the request without answering.
+    Disconnected,
+}
+
+struct Request<Req, Resp> {
+    body: Req,
+    reply: Sender<Resp>,
+}
+
+/// Client
+///
+/// Every call carries its own reply slot, so responses can never be matched to
+/// the wrong request.
+pub struct Client<Req, Resp> {
+    sender: Sender<Request<Req, Resp>>,
+}
+
+impl<Req, Resp> Client<Req, Resp> {
+    pub fn call(&mut self, body: Req) -> Result<Resp, CallError> {
+        let mut reply = self.request(body);
+
+        reply.receive().ok_or(CallError::Disconnected)
+    }
+
+    /// Like `call`, but gives up after `timeout`. A response that arrives later
+    /// is discarded.
+    pub fn call_timeout(&mut self, body: Req, timeout: Duration) -> Result<Resp, CallError> {
+        let mut reply = self.request(body);
+
+        reply.receive_timeout(timeout).map_err(|err| match err {
+            RecvTimeoutError::Timeout => CallError::Timeout,
+            RecvTimeoutError::Disconnected => CallError::Disconnected,
+        })
+    }
+
+    fn request(&mut self, body: Req) -> mpsc::Receiver<Resp> {
+        let (reply, receiver) = mpsc::channel();
+        // Without a server the request is dropped along with `reply`, so the
+        // caller sees `Disconnected`.
+        let _ = self.sender.send(Request { body, reply });
+
+        receiver
+    }
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// Server
+pub struct Server<Req, Resp> {
+    receiver: mpsc::Receiver<Request<Req, Resp>>,
+}
+
+impl<Req: Send + 'static, Resp: Send + 'static> Server<Req, Resp> {
+    /// Answers requests with `handler` on `workers` threads until every client
+    /// has been dropped. A panicking handler only fails the request it was
+    /// handling.
+    pub fn serve<F>(self, workers: usize, handler: F)
+    where
+        F: Fn(Req) -> Resp + Send + Sync + 'static,
+    {
+        let receiver = Arc::new(Mutex::new(self.receiver));
+        let handler = Arc::new(handler);
+
+        let handles: Vec<_> = (0..workers.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+
+                thread::spawn(move || loop {
+                    let Some(mut request) = receiver.lock().unwrap().receive() else {
+                        break;
+                    };
+
+                    let body = request.body;
+                    if let Ok(response) = panic::catch_unwind(AssertUnwindSafe(|| handler(body))) {
+                        // The caller may have timed out already.
+                        let _ = request.reply.send(response);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+pub fn channel<Req, Resp>() -> (Client<Req, Resp>, Server<Req, Resp>) {
+    let (sender, receiver) = mpsc::channel();
+
+    (Client { sender }, Server { receiver })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_call_server() {
+        let (mut client, server) = channel::<i32, i32>();
+        let handle = thread::spawn(move || server.serve(4, |value| value * 2));
+
+        let clients: Vec<_> = (0..8)
+            .map(|value| {
+                let mut client = client.clone();
+                thread::spawn(move || (value, client.call(value)))
+            })
+            .collect();
+
+        for handle in clients {
+            let (value, response) = handle.join().unwrap();
+            assert_eq!(response, Ok(value * 2));
+        }
+
+        assert_eq!(client.call(21), Ok(42));
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn call_can_time_out() {
+        let (mut client, server) = channel::<u64, u64>();
+        thread::spawn(move || {
+            server.serve(1, |millis| {
+                thread::sleep(Duration::from_millis(millis));
+                millis
+            })
+        });
+
+        assert_eq!(
+            client.call_timeout(200, Duration::from_millis(10)),
+            Err(CallError::Timeout)
+        );
+        assert_eq!(client.call_timeout(0, Duration::from_secs(5)), Ok(0));
+    }
+
+    #[test]
+    fn panicking_handler_fails_only_its_request() {
+        let (mut client, server) = channel::<i32, i32>();
+        thread::spawn(move || {
+            server.serve(1, |value| {
+                assert!(value >= 0, "negative request");
+                value
+            })
+        });
+
+        assert_eq!(client.call(-1), Err(CallError::Disconnected));
+        assert_eq!(client.call(1), Ok(1));
+    }
+}
@@ -0,0 +1,322 @@
+//! Producer/consumer scenarios driven by the `run` command.
+//!
+//! Every message carries the instant it was sent, so consumers can record the
+//! time it spent in the channel. A send or receive that cannot complete right
+//! away counts as a block before falling back to the blocking call.
+
+use std::{
+    fmt::{self, Display},
+    sync::{mpsc as std_mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use channels::{
+    lockfree,
+    mpsc::mpsc::{self, RecvTimeoutError, TrySendError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Mutex,
+    LockFree,
+    Std,
+}
+
+impl Backend {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "mutex" => Some(Self::Mutex),
+            "lockfree" => Some(Self::LockFree),
+            "std" => Some(Self::Std),
+            _ => None,
+        }
+    }
+
+    /// The capacity a channel asked for `requested` slots ends up with.
+    pub fn capacity(self, requested: usize) -> usize {
+        match self {
+            Self::Mutex | Self::Std => requested,
+            Self::LockFree => lockfree::rounded_capacity(requested),
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mutex => "mutex",
+            Self::LockFree => "lockfree",
+            Self::Std => "std",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Scenario {
+    pub producers: usize,
+    pub consumers: usize,
+    pub capacity: usize,
+    pub messages: usize,
+    pub backend: Backend,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            producers: 3,
+            consumers: 1,
+            capacity: 3,
+            messages: 1_000_000,
+            backend: Backend::Mutex,
+        }
+    }
+}
+
+/// Report
+pub struct Report {
+    pub elapsed: Duration,
+    pub received: usize,
+    /// Time each message spent between `send` and `receive`, sorted.
+    pub latencies: Vec<Duration>,
+    pub send_blocks: usize,
+    pub receive_blocks: usize,
+}
+
+impl Report {
+    pub fn throughput(&self) -> f64 {
+        self.received as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+enum TryReceive<T> {
+    Value(T),
+    Empty,
+    Disconnected,
+}
+
+trait Producer<T>: Clone + Send + 'static {
+    fn try_send(&mut self, value: T) -> Result<(), T>;
+
+    fn send(&mut self, value: T);
+}
+
+trait Consumer<T>: Send + 'static {
+    fn try_receive(&mut self) -> TryReceive<T>;
+
+    fn receive(&mut self) -> Option<T>;
+}
+
+impl<T: Send + 'static> Producer<T> for mpsc::SyncSender<T> {
+    fn try_send(&mut self, value: T) -> Result<(), T> {
+        match mpsc::SyncSender::try_send(self, value) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => Ok(()),
+            Err(TrySendError::Full(value)) => Err(value),
+        }
+    }
+
+    fn send(&mut self, value: T) {
+        let _ = mpsc::SyncSender::send(self, value);
+    }
+}
+
+impl<T: Send + 'static> Consumer<T> for mpsc::Receiver<T> {
+    fn try_receive(&mut self) -> TryReceive<T> {
+        match self.receive_timeout(Duration::ZERO) {
+            Ok(value) => TryReceive::Value(value),
+            Err(RecvTimeoutError::Timeout) => TryReceive::Empty,
+            Err(RecvTimeoutError::Disconnected) => TryReceive::Disconnected,
+        }
+    }
+
+    fn receive(&mut self) -> Option<T> {
+        mpsc::Receiver::receive(self)
+    }
+}
+
+impl<T: Send + 'static> Producer<T> for lockfree::SyncSender<T> {
+    fn try_send(&mut self, value: T) -> Result<(), T> {
+        match lockfree::SyncSender::try_send(self, value) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => Ok(()),
+            Err(TrySendError::Full(value)) => Err(value),
+        }
+    }
+
+    fn send(&mut self, value: T) {
+        let _ = lockfree::SyncSender::send(self, value);
+    }
+}
+
+impl<T: Send + 'static> Consumer<T> for lockfree::Receiver<T> {
+    fn try_receive(&mut self) -> TryReceive<T> {
+        match lockfree::Receiver::try_receive(self) {
+            Some(value) => TryReceive::Value(value),
+            // Whether senders are gone is settled by the blocking `receive`.
+            None => TryReceive::Empty,
+        }
+    }
+
+    fn receive(&mut self) -> Option<T> {
+        lockfree::Receiver::receive(self)
+    }
+}
+
+impl<T: Send + 'static> Producer<T> for std_mpsc::SyncSender<T> {
+    fn try_send(&mut self, value: T) -> Result<(), T> {
+        match std_mpsc::SyncSender::try_send(self, value) {
+            Ok(()) | Err(std_mpsc::TrySendError::Disconnected(_)) => Ok(()),
+            Err(std_mpsc::TrySendError::Full(value)) => Err(value),
+        }
+    }
+
+    fn send(&mut self, value: T) {
+        let _ = std_mpsc::SyncSender::send(self, value);
+    }
+}
+
+impl<T: Send + 'static> Consumer<T> for std_mpsc::Receiver<T> {
+    fn try_receive(&mut self) -> TryReceive<T> {
+        match self.try_recv() {
+            Ok(value) => TryReceive::Value(value),
+            Err(std_mpsc::TryRecvError::Empty) => TryReceive::Empty,
+            Err(std_mpsc::TryRecvError::Disconnected) => TryReceive::Disconnected,
+        }
+    }
+
+    fn receive(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+pub fn run(scenario: Scenario) -> Report {
+    match scenario.backend {
+        Backend::Mutex => run_with(scenario, mpsc::sync_channel(scenario.capacity)),
+        Backend::LockFree => run_with(scenario, lockfree::sync_channel(scenario.capacity)),
+        Backend::Std => run_with(scenario, std_mpsc::sync_channel(scenario.capacity)),
+    }
+}
+
+struct Consumed {
+    latencies: Vec<Duration>,
+    blocks: usize,
+}
+
+fn run_with<P, C>(scenario: Scenario, (sender, receiver): (P, C)) -> Report
+where
+    P: Producer<Instant>,
+    C: Consumer<Instant>,
+{
+    let producers = scenario.producers.max(1);
+    let consumers = scenario.consumers.max(1);
+
+    // Receivers are single-consumer, so several consumers take turns on it.
+    let receiver = Arc::new(Mutex::new(receiver));
+    let started = Instant::now();
+
+    let producer_handles: Vec<_> = (0..producers)
+        .map(|index| {
+            let mut sender = sender.clone();
+            let count =
+                scenario.messages / producers + usize::from(index < scenario.messages % producers);
+
+            thread::spawn(move || {
+                let mut blocks = 0;
+                for _ in 0..count {
+                    if let Err(stamp) = sender.try_send(Instant::now()) {
+                        blocks += 1;
+                        sender.send(stamp);
+                    }
+                }
+                blocks
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let consumer_handles: Vec<_> = (0..consumers)
+        .map(|_| {
+            let receiver = Arc::clone(&receiver);
+
+            thread::spawn(move || {
+                let mut consumed = Consumed {
+                    latencies: Vec::with_capacity(scenario.messages / consumers),
+                    blocks: 0,
+                };
+
+                loop {
+                    let mut receiver = receiver.lock().unwrap();
+                    let stamp = match receiver.try_receive() {
+                        TryReceive::Value(stamp) => stamp,
+                        TryReceive::Disconnected => break,
+                        TryReceive::Empty => {
+                            consumed.blocks += 1;
+                            match receiver.receive() {
+                                Some(stamp) => stamp,
+                                None => break,
+                            }
+                        }
+                    };
+                    drop(receiver);
+
+                    consumed.latencies.push(stamp.elapsed());
+                }
+
+                consumed
+            })
+        })
+        .collect();
+
+    let send_blocks = producer_handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .sum();
+
+    let mut latencies = Vec::with_capacity(scenario.messages);
+    let mut receive_blocks = 0;
+    for handle in consumer_handles {
+        let consumed = handle.join().unwrap();
+        latencies.extend(consumed.latencies);
+        receive_blocks += consumed.blocks;
+    }
+    let elapsed = started.elapsed();
+
+    latencies.sort_unstable();
+
+    Report {
+        elapsed,
+        received: latencies.len(),
+        latencies,
+        send_blocks,
+        receive_blocks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_backend_delivers_all_messages() {
+        for backend in [Backend::Mutex, Backend::LockFree, Backend::Std] {
+            let report = run(Scenario {
+                producers: 4,
+                consumers: 2,
+                capacity: 8,
+                messages: 10_001,
+                backend,
+            });
+
+            assert_eq!(report.received, 10_001, "{backend}");
+            assert!(report.percentile(50.0) <= report.percentile(99.0));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use crate::mpsc::mpsc::{Inner, SendError};
+
+/// Sender
+///
+/// Pinned to a single shard for its whole life, so messages from one sender
+/// (and thus from the thread that owns it) are received in the order they were
+/// sent. Each clone is pinned to the next shard.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    shard: usize,
+}
+
+impl<T> Sender<T> {
+    /// Fails, handing the value back, once the receiver is gone.
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        let mut inner = self.shared.shards[self.shard].lock().unwrap();
+        if inner.closed {
+            return Err(SendError(value));
+        }
+        inner.queue.push_back(value);
+
+        drop(inner);
+
+        self.shared.wake_receiver();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let shard =
+            self.shared.next_shard.fetch_add(1, Ordering::Relaxed) % self.shared.shards.len();
+
+        let mut inner = self.shared.shards[shard].lock().unwrap();
+        inner.senders += 1;
+        drop(inner);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+            shard,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.shards[self.shard].lock().unwrap();
+        inner.senders -= 1;
+        drop(inner);
+
+        self.shared.wake_receiver();
+    }
+}
+
+/// Receiver
+///
+/// Visits the shards round-robin, taking everything queued in a shard at once.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    buffer: VecDeque<T>,
+    cursor: usize,
+}
+
+impl<T> Receiver<T> {
+    pub fn receive(&mut self) -> Option<T> {
+        loop {
+            if let Some(data) = self.buffer.pop_front() {
+                return Some(data);
+            }
+
+            if self.take_next_shard() {
+                continue;
+            }
+
+            let mut sleeping = self.shared.signal.lock().unwrap();
+            *sleeping = true;
+            self.shared.receiver_sleeping.store(true, Ordering::SeqCst);
+
+            // Senders push before checking `receiver_sleeping`, so anything
+            // sent before the flag was raised is visible to this re-check.
+            let mut senders = 0;
+            let mut found = false;
+            for shard in &self.shared.shards {
+                let inner = shard.lock().unwrap();
+                senders += inner.senders;
+                found |= !inner.queue.is_empty();
+            }
+
+            if !found && senders == 0 {
+                self.shared.receiver_sleeping.store(false, Ordering::SeqCst);
+                return None;
+            }
+
+            if !found {
+                while *sleeping {
+                    sleeping = self.shared.receivers_available.wait(sleeping).unwrap();
+                }
+            }
+
+            *sleeping = false;
+            self.shared.receiver_sleeping.store(false, Ordering::SeqCst);
+        }
+    }
+
+    fn take_next_shard(&mut self) -> bool {
+        let shards = self.shared.shards.len();
+
+        for offset in 0..shards {
+            let index = (self.cursor + offset) % shards;
+            let mut inner = self.shared.shards[index].lock().unwrap();
+
+            if !inner.queue.is_empty() {
+                std::mem::swap(&mut inner.queue, &mut self.buffer);
+                self.cursor = (index + 1) % shards;
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        for shard in &self.shared.shards {
+            let mut inner = shard.lock().unwrap();
+            inner.closed = true;
+
+            let queue = std::mem::take(&mut inner.queue);
+
+            drop(inner);
+            drop(queue);
+        }
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+struct Shared<T> {
+    shards: Vec<Mutex<Inner<T>>>,
+    next_shard: AtomicUsize,
+    receiver_sleeping: AtomicBool,
+    signal: Mutex<bool>,
+    receivers_available: Condvar,
+}
+
+impl<T> Shared<T> {
+    /// Only touches the signal lock when the receiver is actually parked, so
+    /// senders on different shards never contend on a common mutex otherwise.
+    fn wake_receiver(&self) {
+        if self.receiver_sleeping.load(Ordering::SeqCst) {
+            let mut sleeping = self.signal.lock().unwrap();
+            *sleeping = false;
+            drop(sleeping);
+
+            self.receivers_available.notify_one();
+        }
+    }
+}
+
+/// Creates an unbounded channel spread over `shards` independent queues.
+pub fn channel<T>(shards: usize) -> (Sender<T>, Receiver<T>) {
+    let shards = (0..shards.max(1))
+        .map(|_| {
+            Mutex::new(Inner {
+                queue: VecDeque::new(),
+                senders: 0,
+                closed: false,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let shared = Shared {
+        shards,
+        next_shard: AtomicUsize::new(1),
+        receiver_sleeping: AtomicBool::new(false),
+        signal: Mutex::new(false),
+        receivers_available: Condvar::default(),
+    };
+    shared.shards[0].lock().unwrap().senders = 1;
+    let shared = Arc::new(shared);
+
+    (
+        Sender {
+            shared: shared.clone(),
+            shard: 0,
+        },
+        Receiver {
+            shared,
+            buffer: VecDeque::default(),
+            cursor: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn preserves_per_producer_order() {
+        let (sender, receiver) = channel::<(usize, usize)>(4);
+
+        let handles: Vec<_> = (0..8)
+            .map(|producer| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for seq in 0..1000 {
+                        sender.send((producer, seq)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut next = [0; 8];
+        for (producer, seq) in receiver {
+            assert_eq!(seq, next[producer]);
+            next[producer] += 1;
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(next, [1000; 8]);
+    }
+
+    #[test]
+    fn disconnects_when_all_senders_drop() {
+        let (mut sender, mut receiver) = channel(2);
+        let mut other = sender.clone();
+
+        sender.send(1).unwrap();
+        other.send(2).unwrap();
+        drop(sender);
+        drop(other);
+
+        let mut received = vec![receiver.receive().unwrap(), receiver.receive().unwrap()];
+        received.sort();
+
+        assert_eq!(received, vec![1, 2]);
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn send_fails_once_the_receiver_is_gone() {
+        let (mut sender, receiver) = channel(2);
+        drop(receiver);
+
+        assert_eq!(sender.send(1), Err(SendError(1)));
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    sync::mpsc as std_mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    lockfree,
+    mpsc::mpsc::{self, SendError},
+    sharded,
+};
+
+/// SendChannel
+///
+/// Sending half of any channel backend. Like `mpsc::Sender`, fails once the
+/// receiver is gone, handing the value back.
+pub trait SendChannel<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>>;
+}
+
+/// RecvChannel
+///
+/// Receiving half of any channel backend. Returns `None` once every sender is
+/// gone and the channel is drained.
+pub trait RecvChannel<T> {
+    fn receive(&mut self) -> Option<T>;
+}
+
+impl<T> SendChannel<T> for mpsc::Sender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        mpsc::Sender::send(self, value)
+    }
+}
+
+impl<T> SendChannel<T> for mpsc::SyncSender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        mpsc::SyncSender::send(self, value)
+    }
+}
+
+impl<T> RecvChannel<T> for mpsc::Receiver<T> {
+    fn receive(&mut self) -> Option<T> {
+        mpsc::Receiver::receive(self)
+    }
+}
+
+impl<T> SendChannel<T> for sharded::Sender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        sharded::Sender::send(self, value)
+    }
+}
+
+impl<T> RecvChannel<T> for sharded::Receiver<T> {
+    fn receive(&mut self) -> Option<T> {
+        sharded::Receiver::receive(self)
+    }
+}
+
+impl<T> SendChannel<T> for lockfree::SyncSender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        lockfree::SyncSender::send(self, value)
+    }
+}
+
+impl<T> RecvChannel<T> for lockfree::Receiver<T> {
+    fn receive(&mut self) -> Option<T> {
+        lockfree::Receiver::receive(self)
+    }
+}
+
+impl<T> SendChannel<T> for std_mpsc::Sender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        std_mpsc::Sender::send(self, value).map_err(|std_mpsc::SendError(value)| SendError(value))
+    }
+}
+
+impl<T> SendChannel<T> for std_mpsc::SyncSender<T> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        std_mpsc::SyncSender::send(self, value)
+            .map_err(|std_mpsc::SendError(value)| SendError(value))
+    }
+}
+
+impl<T> RecvChannel<T> for std_mpsc::Receiver<T> {
+    fn receive(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+impl<T, S: SendChannel<T> + ?Sized> SendChannel<T> for Box<S> {
+    fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        (**self).send(value)
+    }
+}
+
+impl<T, R: RecvChannel<T> + ?Sized> RecvChannel<T> for Box<R> {
+    fn receive(&mut self) -> Option<T> {
+        (**self).receive()
+    }
+}
+
+/// Backend
+///
+/// A family of channels. Code generic over `B: Backend` can be switched between
+/// implementations, e.g. to compare them in benchmarks.
+pub trait Backend {
+    type Sender<T: Send + 'static>: SendChannel<T> + Clone + Send + 'static;
+    type SyncSender<T: Send + 'static>: SendChannel<T> + Clone + Send + 'static;
+    type Receiver<T: Send + 'static>: RecvChannel<T> + Send + 'static;
+
+    fn channel<T: Send + 'static>() -> (Self::Sender<T>, Self::Receiver<T>);
+
+    fn sync_channel<T: Send + 'static>(capacity: usize)
+        -> (Self::SyncSender<T>, Self::Receiver<T>);
+}
+
+/// The `Mutex<Inner<T>>` based channels of this crate.
+pub struct MutexBackend;
+
+impl Backend for MutexBackend {
+    type Sender<T: Send + 'static> = mpsc::Sender<T>;
+    type SyncSender<T: Send + 'static> = mpsc::SyncSender<T>;
+    type Receiver<T: Send + 'static> = mpsc::Receiver<T>;
+
+    fn channel<T: Send + 'static>() -> (Self::Sender<T>, Self::Receiver<T>) {
+        mpsc::channel()
+    }
+
+    fn sync_channel<T: Send + 'static>(
+        capacity: usize,
+    ) -> (Self::SyncSender<T>, Self::Receiver<T>) {
+        mpsc::sync_channel(capacity)
+    }
+}
+
+/// The channels of `std::sync::mpsc`.
+pub struct StdBackend;
+
+impl Backend for StdBackend {
+    type Sender<T: Send + 'static> = std_mpsc::Sender<T>;
+    type SyncSender<T: Send + 'static> = std_mpsc::SyncSender<T>;
+    type Receiver<T: Send + 'static> = std_mpsc::Receiver<T>;
+
+    fn channel<T: Send + 'static>() -> (Self::Sender<T>, Self::Receiver<T>) {
+        std_mpsc::channel()
+    }
+
+    fn sync_channel<T: Send + 'static>(
+        capacity: usize,
+    ) -> (Self::SyncSender<T>, Self::Receiver<T>) {
+        std_mpsc::sync_channel(capacity)
+    }
+}
+
+/// Moves every value from `receiver` into `sender` on a background thread,
+/// until `receiver` is disconnected or whoever receives from `sender` is gone.
+pub fn bridge<T, R, S>(mut receiver: R, mut sender: S) -> JoinHandle<()>
+where
+    T: Send + 'static,
+    R: RecvChannel<T> + Send + 'static,
+    S: SendChannel<T> + Send + 'static,
+{
+    thread::spawn(move || {
+        while let Some(value) = receiver.receive() {
+            if sender.send(value).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// Exposes any receiver as a `std::sync::mpsc::Receiver`.
+pub fn into_std<T, R>(receiver: R) -> std_mpsc::Receiver<T>
+where
+    T: Send + 'static,
+    R: RecvChannel<T> + Send + 'static,
+{
+    let (sender, std_receiver) = std_mpsc::channel();
+    bridge(receiver, sender);
+
+    std_receiver
+}
+
+/// Exposes any receiver as an `mpsc::Receiver` of this crate.
+pub fn into_mpsc<T, R>(receiver: R) -> mpsc::Receiver<T>
+where
+    T: Send + 'static,
+    R: RecvChannel<T> + Send + 'static,
+{
+    let (sender, mpsc_receiver) = mpsc::channel();
+    bridge(receiver, sender);
+
+    mpsc_receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn produce_and_sum<B: Backend>() -> u64 {
+        let (sender, mut receiver) = B::sync_channel::<u64>(4);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for value in 1..=100 {
+                        sender.send(value).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut sum = 0;
+        while let Some(value) = receiver.receive() {
+            sum += value;
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        sum
+    }
+
+    #[test]
+    fn backends_are_interchangeable() {
+        assert_eq!(produce_and_sum::<MutexBackend>(), 4 * 5050);
+        assert_eq!(produce_and_sum::<StdBackend>(), 4 * 5050);
+    }
+
+    #[test]
+    fn can_convert_between_families() {
+        let (mut sender, receiver) = mpsc::channel();
+        let std_receiver = into_std(receiver);
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        drop(sender);
+
+        let mut back = into_mpsc(std_receiver);
+        assert_eq!(back.receive(), Some(1));
+        assert_eq!(back.receive(), Some(2));
+        assert_eq!(back.receive(), None);
+    }
+
+    #[test]
+    fn bridge_stops_once_the_output_is_dropped() {
+        let (mut sender, receiver) = mpsc::channel();
+        let (output, output_receiver) = mpsc::channel::<i32>();
+
+        let handle = bridge(receiver, output);
+        drop(output_receiver);
+        sender.send(1).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(sender.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn can_box_senders() {
+        let (std_sender, std_receiver) = std_mpsc::channel();
+        let (mpsc_sender, mpsc_receiver) = mpsc::channel();
+
+        let mut senders: Vec<Box<dyn SendChannel<i32>>> =
+            vec![Box::new(std_sender), Box::new(mpsc_sender)];
+        for sender in senders.iter_mut() {
+            sender.send(7).unwrap();
+        }
+        drop(senders);
+
+        assert_eq!(std_receiver.iter().collect::<Vec<_>>(), vec![7]);
+        assert_eq!(mpsc_receiver.collect::<Vec<_>>(), vec![7]);
+    }
+}
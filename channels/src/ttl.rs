@@ -0,0 +1,162 @@
+use std::time::{Duration, Instant};
+
+use crate::mpsc::mpsc::{self, SendError, SyncSender};
+
+struct Expiring<T> {
+    value: T,
+    deadline: Option<Instant>,
+}
+
+/// Sender
+///
+/// Bounded sender whose messages may carry a time-to-live.
+pub struct Sender<T> {
+    sender: SyncSender<Expiring<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a message that never expires.
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        self.send_expiring(Expiring {
+            value,
+            deadline: None,
+        })
+    }
+
+    /// Sends a message that is discarded if it is still queued after `ttl`.
+    /// A `ttl` too large to represent, like `Duration::MAX`, never expires.
+    pub fn send_with_ttl(&mut self, value: T, ttl: Duration) -> Result<(), SendError<T>> {
+        self.send_expiring(Expiring {
+            value,
+            deadline: Instant::now().checked_add(ttl),
+        })
+    }
+
+    fn send_expiring(&mut self, message: Expiring<T>) -> Result<(), SendError<T>> {
+        self.sender
+            .send(message)
+            .map_err(|SendError(message)| SendError(message.value))
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// Receiver
+///
+/// Skips messages whose time-to-live ran out while they were queued. Expired
+/// messages are counted and, if configured, forwarded to a dead-letter
+/// channel.
+pub struct Receiver<T> {
+    receiver: mpsc::Receiver<Expiring<T>>,
+    dead_letter: Option<mpsc::Sender<T>>,
+    expired: usize,
+}
+
+impl<T> Receiver<T> {
+    pub fn receive(&mut self) -> Option<T> {
+        loop {
+            let message = self.receiver.receive()?;
+
+            match message.deadline {
+                Some(deadline) if deadline <= Instant::now() => {
+                    self.expired += 1;
+
+                    if let Some(dead_letter) = self.dead_letter.as_mut() {
+                        let _ = dead_letter.send(message.value);
+                    }
+                }
+                _ => return Some(message.value),
+            }
+        }
+    }
+
+    /// Number of messages discarded because they expired.
+    pub fn expired(&self) -> usize {
+        self.expired
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive()
+    }
+}
+
+pub fn sync_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+
+    (
+        Sender { sender },
+        Receiver {
+            receiver,
+            dead_letter: None,
+            expired: 0,
+        },
+    )
+}
+
+/// Like `sync_channel`, plus an unbounded dead-letter receiver collecting every
+/// expired message.
+pub fn sync_channel_with_dead_letter<T>(
+    capacity: usize,
+) -> (Sender<T>, Receiver<T>, mpsc::Receiver<T>) {
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let (dead_letter, dead_letters) = mpsc::channel();
+
+    (
+        Sender { sender },
+        Receiver {
+            receiver,
+            dead_letter: Some(dead_letter),
+            expired: 0,
+        },
+        dead_letters,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn discards_expired_messages() {
+        let (mut sender, mut receiver) = sync_channel(4);
+
+        sender.send_with_ttl(1, Duration::ZERO).unwrap();
+        sender.send(2).unwrap();
+        sender.send_with_ttl(3, Duration::from_secs(60)).unwrap();
+        sender.send_with_ttl(4, Duration::MAX).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(receiver.receive(), Some(3));
+        assert_eq!(receiver.receive(), Some(4));
+        assert_eq!(receiver.receive(), None);
+        assert_eq!(receiver.expired(), 1);
+    }
+
+    #[test]
+    fn dead_letter_collects_expired_messages() {
+        let (mut sender, receiver, dead_letters) = sync_channel_with_dead_letter(4);
+
+        sender
+            .send_with_ttl("stale", Duration::from_millis(1))
+            .unwrap();
+        sender.send("fresh").unwrap();
+        drop(sender);
+        thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(receiver.collect::<Vec<_>>(), vec!["fresh"]);
+        assert_eq!(dead_letters.collect::<Vec<_>>(), vec!["stale"]);
+    }
+}